@@ -55,7 +55,7 @@ impl Generator {
 
             //self.generate_line(&mut line_buffer, params.template())?;
 
-            line_buffer.push_str("\n");
+            line_buffer.push('\n');
             w.write_all(line_buffer.as_bytes())?;
 
             lines_generated += 1;
@@ -0,0 +1,212 @@
+use std::fmt::Write;
+
+use jiff::{SignedDuration, Timestamp};
+
+// --------------------------------------------------------------------------
+
+/// What the time column shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum TimeMode {
+    /// The timestamp of the line
+    #[default]
+    Absolute,
+    /// The time since the first line
+    Relative,
+    /// The time since the previous line, or the start of the session
+    Delta,
+    /// The time since the start of the session
+    Session,
+}
+
+/// What the clock makes of the timestamp of a line
+#[derive(Debug, Default)]
+pub(crate) struct Tick {
+    /// The time to show in place of the timestamp, if not absolute
+    pub elapsed: Option<SignedDuration>,
+    /// The time since the previous line
+    pub delta: Option<SignedDuration>,
+}
+
+impl Tick {
+    /// Checks if the time since the previous line is over `threshold`, where
+    /// no threshold (or no previous line) is never exceeded
+    pub fn exceeds(&self, threshold: Option<SignedDuration>) -> bool {
+        match (self.delta, threshold) {
+            (Some(delta), Some(threshold)) => delta > threshold,
+            _ => false,
+        }
+    }
+}
+
+/// Keeps track of the timestamps of the lines shown so far, to work out the
+/// elapsed times shown by the relative time modes. Lines without a (valid)
+/// timestamp are left out.
+#[derive(Debug, Default)]
+pub(crate) struct Clock {
+    first: Option<Timestamp>,
+    session_start: Option<Timestamp>,
+    previous: Option<Timestamp>,
+}
+
+impl Clock {
+    /// Starts a new session, the next timestamp starts the session clock. The
+    /// previous line is kept, so a pause before the session still counts as
+    /// a gap.
+    pub fn start_session(&mut self) {
+        self.session_start = None;
+    }
+
+    /// Advances the clock to the timestamp of the next line shown
+    pub fn tick(&mut self, mode: TimeMode, ts: Option<Timestamp>) -> Tick {
+        let Some(ts) = ts else {
+            return Tick::default();
+        };
+        let first = *self.first.get_or_insert(ts);
+        let starts_session = self.session_start.is_none();
+        let session_start = *self.session_start.get_or_insert(ts);
+        let delta = self
+            .previous
+            .replace(ts)
+            .map(|previous| ts.duration_since(previous));
+
+        let elapsed = match mode {
+            TimeMode::Absolute => None,
+            TimeMode::Relative => Some(ts.duration_since(first)),
+            TimeMode::Delta if starts_session => Some(SignedDuration::ZERO),
+            TimeMode::Delta => Some(delta.unwrap_or_default()),
+            TimeMode::Session => Some(ts.duration_since(session_start)),
+        };
+        Tick { elapsed, delta }
+    }
+}
+
+/// Writes an elapsed time like '+0.153s', '+4m12.000s' or '+1h00m05.250s',
+/// right-aligned in a column of `ELAPSED_WIDTH` characters
+pub(crate) fn write_elapsed(dest: &mut String, elapsed: SignedDuration) {
+    const ELAPSED_WIDTH: usize = 10;
+
+    let sign = if elapsed.is_negative() { '-' } else { '+' };
+    let millis = elapsed.as_millis().unsigned_abs();
+    let (hours, minutes) = (millis / 3_600_000, millis / 60_000 % 60);
+    let (seconds, millis) = (millis / 1000 % 60, millis % 1000);
+
+    let mut text = String::with_capacity(ELAPSED_WIDTH);
+    text.push(sign);
+    if hours > 0 {
+        write!(text, "{hours}h{minutes:02}m{seconds:02}").unwrap();
+    } else if minutes > 0 {
+        write!(text, "{minutes}m{seconds:02}").unwrap();
+    } else {
+        write!(text, "{seconds}").unwrap();
+    }
+    write!(text, ".{millis:03}s").unwrap();
+    write!(dest, "{text:>ELAPSED_WIDTH$}").unwrap();
+}
+
+/// Writes the length of a gap between lines like '750ms', '45s', '4m12s' or
+/// '1h00m05s'
+pub(crate) fn write_gap(dest: &mut String, gap: SignedDuration) {
+    let millis = gap.as_millis().unsigned_abs();
+    let seconds = millis / 1000;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+    if hours > 0 {
+        write!(dest, "{hours}h{minutes:02}m{seconds:02}s").unwrap();
+    } else if minutes > 0 {
+        write!(dest, "{minutes}m{seconds:02}s").unwrap();
+    } else if seconds > 0 {
+        write!(dest, "{seconds}s").unwrap();
+    } else {
+        write!(dest, "{millis}ms").unwrap();
+    }
+}
+
+// --------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(seconds: f64) -> Option<Timestamp> {
+        Some(Timestamp::from_millisecond((seconds * 1000.0) as i64).unwrap())
+    }
+
+    fn elapsed(elapsed: SignedDuration) -> String {
+        let mut dest = String::new();
+        write_elapsed(&mut dest, elapsed);
+        dest
+    }
+
+    #[test]
+    fn test_write_elapsed() {
+        assert_eq!(elapsed(SignedDuration::from_millis(153)), "   +0.153s");
+        assert_eq!(elapsed(SignedDuration::from_secs(252)), "+4m12.000s");
+        assert_eq!(
+            elapsed(SignedDuration::from_millis(3_605_250)),
+            "+1h00m05.250s"
+        );
+        assert_eq!(elapsed(SignedDuration::from_millis(-20)), "   -0.020s");
+    }
+
+    #[test]
+    fn test_write_gap() {
+        let gap = |gap| {
+            let mut dest = String::new();
+            write_gap(&mut dest, gap);
+            dest
+        };
+        assert_eq!(gap(SignedDuration::from_millis(750)), "750ms");
+        assert_eq!(gap(SignedDuration::from_millis(45_120)), "45s");
+        assert_eq!(gap(SignedDuration::from_secs(252)), "4m12s");
+        assert_eq!(gap(SignedDuration::from_secs(3605)), "1h00m05s");
+    }
+
+    #[test]
+    fn test_clock_modes() {
+        let elapsed = |mode| {
+            let mut clock = Clock::default();
+            let mut ticks = vec![clock.tick(mode, ts(10.0)), clock.tick(mode, ts(10.5))];
+            clock.start_session();
+            ticks.push(clock.tick(mode, ts(12.0)));
+            ticks.push(clock.tick(mode, None));
+            ticks.push(clock.tick(mode, ts(13.0)));
+            ticks
+                .into_iter()
+                .map(|tick| tick.elapsed.map(|e| e.as_millis()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            elapsed(TimeMode::Relative),
+            [Some(0), Some(500), Some(2000), None, Some(3000)]
+        );
+        assert_eq!(
+            elapsed(TimeMode::Delta),
+            [Some(0), Some(500), Some(0), None, Some(1000)]
+        );
+        assert_eq!(
+            elapsed(TimeMode::Session),
+            [Some(0), Some(500), Some(0), None, Some(1000)]
+        );
+        assert_eq!(elapsed(TimeMode::Absolute), [None; 5]);
+    }
+
+    #[test]
+    fn test_clock_deltas() {
+        let mut clock = Clock::default();
+        assert_eq!(clock.tick(TimeMode::Absolute, ts(1.0)).delta, None);
+        let delta = clock.tick(TimeMode::Absolute, ts(1.25)).delta;
+        assert_eq!(delta, Some(SignedDuration::from_millis(250)));
+
+        // the delta goes across sessions, even if the delta shown does not
+        clock.start_session();
+        let tick = clock.tick(TimeMode::Delta, ts(31.25));
+        assert_eq!(tick.delta, Some(SignedDuration::from_secs(30)));
+        assert_eq!(tick.elapsed, Some(SignedDuration::ZERO));
+
+        let second = Some(SignedDuration::from_secs(1));
+        assert!(tick.exceeds(second));
+        assert!(!tick.exceeds(Some(SignedDuration::from_secs(30))));
+        assert!(!tick.exceeds(None));
+        assert!(!Tick::default().exceeds(second));
+    }
+}
@@ -0,0 +1,203 @@
+use std::io::{self, BufRead, BufReader, Chain, Cursor, Read};
+
+// --------------------------------------------------------------------------
+
+/// The compression formats we can read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    /// The longest magic number we look for
+    const MAGIC_LEN: usize = 6;
+
+    /// Detects the compression format from the first bytes of the input
+    fn detect(header: &[u8]) -> Self {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if header.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else {
+            Compression::None
+        }
+    }
+
+    /// The name of the format, for messages
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Bzip2 => "bzip2",
+            Compression::Xz => "xz",
+        }
+    }
+}
+
+/// An input, with the bytes read ahead (i.e. to detect the compression) put
+/// back in front
+pub(crate) type Peeked<R> = Chain<Cursor<Vec<u8>>, R>;
+
+/// Reads an input that may be compressed, decompressing it on the fly. The
+/// compression is detected from the magic number at the start of the input,
+/// so it works for std in as well as for files with any name.
+///
+/// Concatenated compressed streams (i.e. from `cat a.gz b.gz`) are read as
+/// one.
+///
+pub struct DecompressReader<R: BufRead> {
+    inner: Inner<R>,
+}
+
+enum Inner<R: BufRead> {
+    Plain(Peeked<R>),
+    Gzip(BufReader<flate2::bufread::MultiGzDecoder<Peeked<R>>>),
+    Zstd(BufReader<zstd::stream::read::Decoder<'static, Peeked<R>>>),
+    Bzip2(BufReader<bzip2::bufread::MultiBzDecoder<Peeked<R>>>),
+    Xz(BufReader<liblzma::bufread::XzDecoder<Peeked<R>>>),
+}
+
+impl<R: BufRead> DecompressReader<R> {
+    /// Wraps `reader`, reading just enough of it to detect the compression
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = Vec::with_capacity(Compression::MAGIC_LEN);
+        (&mut reader)
+            .take(Compression::MAGIC_LEN as u64)
+            .read_to_end(&mut header)?;
+        let compression = Compression::detect(&header);
+        let reader = Cursor::new(header).chain(reader);
+
+        let inner = match compression {
+            Compression::None => Inner::Plain(reader),
+            Compression::Gzip => {
+                Inner::Gzip(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader)))
+            }
+            Compression::Zstd => Inner::Zstd(BufReader::new(
+                zstd::stream::read::Decoder::with_buffer(reader)?,
+            )),
+            Compression::Bzip2 => {
+                Inner::Bzip2(BufReader::new(bzip2::bufread::MultiBzDecoder::new(reader)))
+            }
+            Compression::Xz => Inner::Xz(BufReader::new(
+                liblzma::bufread::XzDecoder::new_multi_decoder(reader),
+            )),
+        };
+        Ok(Self { inner })
+    }
+
+    /// The compression detected for the input
+    pub fn compression(&self) -> Compression {
+        match self.inner {
+            Inner::Plain(_) => Compression::None,
+            Inner::Gzip(_) => Compression::Gzip,
+            Inner::Zstd(_) => Compression::Zstd,
+            Inner::Bzip2(_) => Compression::Bzip2,
+            Inner::Xz(_) => Compression::Xz,
+        }
+    }
+}
+
+/// Forwards a call to the reader of whichever format we are reading
+macro_rules! forward {
+    ($self:ident, $reader:ident => $call:expr) => {
+        match &mut $self.inner {
+            Inner::Plain($reader) => $call,
+            Inner::Gzip($reader) => $call,
+            Inner::Zstd($reader) => $call,
+            Inner::Bzip2($reader) => $call,
+            Inner::Xz($reader) => $call,
+        }
+    };
+}
+
+impl<R: BufRead> Read for DecompressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        forward!(self, reader => reader.read(buf))
+    }
+}
+
+impl<R: BufRead> BufRead for DecompressReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        forward!(self, reader => reader.fill_buf())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        forward!(self, reader => reader.consume(amt))
+    }
+}
+
+// --------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    const LINES: &str = "{\"message\":\"one\"}\n{\"message\":\"two\"}\n";
+
+    fn read_all(input: Vec<u8>) -> (Compression, String) {
+        let mut reader = DecompressReader::new(Cursor::new(input)).unwrap();
+        let mut output = String::new();
+        reader.read_to_string(&mut output).unwrap();
+        (reader.compression(), output)
+    }
+
+    #[test]
+    fn test_plain_input() {
+        assert_eq!(
+            read_all(LINES.as_bytes().to_vec()),
+            (Compression::None, LINES.to_string())
+        );
+        assert_eq!(
+            read_all(b"{}".to_vec()),
+            (Compression::None, "{}".to_string())
+        );
+        assert_eq!(read_all(Vec::new()), (Compression::None, String::new()));
+    }
+
+    #[test]
+    fn test_gzip_input() {
+        let gzip = |s: &str| {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(s.as_bytes()).unwrap();
+            encoder.finish().unwrap()
+        };
+        // concatenated members, like 'cat a.gz b.gz'
+        let (first, second) = LINES.split_at(18);
+        let input = [gzip(first), gzip(second)].concat();
+        assert_eq!(read_all(input), (Compression::Gzip, LINES.to_string()));
+    }
+
+    #[test]
+    fn test_zstd_input() {
+        let input = zstd::encode_all(LINES.as_bytes(), 0).unwrap();
+        assert_eq!(read_all(input), (Compression::Zstd, LINES.to_string()));
+    }
+
+    #[test]
+    fn test_bzip2_input() {
+        let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        encoder.write_all(LINES.as_bytes()).unwrap();
+        let input = encoder.finish().unwrap();
+        assert_eq!(read_all(input), (Compression::Bzip2, LINES.to_string()));
+    }
+
+    #[test]
+    fn test_xz_input() {
+        let mut encoder = liblzma::write::XzEncoder::new(Vec::new(), 6);
+        encoder.write_all(LINES.as_bytes()).unwrap();
+        let input = encoder.finish().unwrap();
+        assert_eq!(read_all(input), (Compression::Xz, LINES.to_string()));
+    }
+}
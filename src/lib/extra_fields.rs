@@ -0,0 +1,337 @@
+use std::{borrow::Cow, fmt::Write};
+
+use sonic_rs::{JsonValueTrait, LazyValue};
+
+use crate::fields::{FieldMap, FieldPath};
+
+// --------------------------------------------------------------------------
+
+/// How to render fields holding JSON objects or arrays
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum NestedStyle {
+    /// As compact JSON, in line with the other fields
+    #[default]
+    Inline,
+    /// As indented, pretty-printed JSON on the lines following the log line
+    Pretty,
+}
+
+/// Selects which of the fields of a log line, beyond the timestamp, level and
+/// message, are rendered after the message, and how.
+#[derive(Debug, Clone, Default)]
+pub struct ExtraFields {
+    only: Option<Vec<FieldPath>>,
+    hidden: Vec<FieldPath>,
+    nested: NestedStyle,
+}
+
+impl ExtraFields {
+    /// Renders all the remaining top-level fields of each line
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Renders only the fields at the given paths, in that order
+    pub fn only(paths: Vec<FieldPath>) -> Self {
+        Self {
+            only: Some(paths),
+            ..Self::default()
+        }
+    }
+
+    /// Never renders the given fields. Nested fields are left out of the
+    /// objects holding them.
+    pub fn with_hidden(mut self, paths: Vec<FieldPath>) -> Self {
+        self.hidden = paths;
+        self
+    }
+
+    /// Sets how objects and arrays are rendered
+    pub fn with_nested(mut self, nested: NestedStyle) -> Self {
+        self.nested = nested;
+        self
+    }
+
+    /// Writes the selected fields of `line` to `dest` as ` key=value` pairs.
+    /// Nested values rendered in the pretty style are written to `nested`
+    /// instead, to be emitted on the lines after the log line.
+    ///
+    /// Fields used by the `fields` map (i.e. the message) are skipped, unless
+    /// explicitly selected.
+    pub(crate) fn write(
+        &self,
+        dest: &mut String,
+        nested: &mut String,
+        line: &str,
+        fields: &FieldMap,
+        colors: &FieldColors,
+    ) {
+        match &self.only {
+            Some(paths) => {
+                for path in paths.iter().filter(|path| !self.hidden.contains(path)) {
+                    if let Some(value) = path.lookup(line) {
+                        let key = path.to_string();
+                        self.write_visible(dest, nested, &key, path.segments(), &value, colors);
+                    }
+                }
+            }
+            None => {
+                for entry in sonic_rs::to_object_iter(line) {
+                    let Ok((key, value)) = entry else {
+                        break;
+                    };
+                    if !self.is_hidden(&key) && !is_used(&key, fields) {
+                        self.write_visible(dest, nested, &key, &[&*key], &value, colors);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Writes a single ` key=value` pair for the field at the path `segments`,
+    /// leaving out the fields hidden within it
+    fn write_visible<S: AsRef<str>>(
+        &self,
+        dest: &mut String,
+        nested: &mut String,
+        key: &str,
+        segments: &[S],
+        value: &LazyValue,
+        colors: &FieldColors,
+    ) {
+        let mut hidden = self
+            .hidden
+            .iter()
+            .filter_map(|path| {
+                let (head, rest) = path.segments().split_at_checked(segments.len())?;
+                let is_within = head
+                    .iter()
+                    .map(String::as_str)
+                    .eq(segments.iter().map(AsRef::as_ref));
+                (is_within && !rest.is_empty()).then_some(rest)
+            })
+            .peekable();
+        if hidden.peek().is_none() || !value.is_object() {
+            self.write_field(dest, nested, key, value, colors);
+            return;
+        }
+
+        let mut visible = String::new();
+        write_without(&mut visible, value, &hidden.collect::<Vec<_>>());
+        match sonic_rs::from_str::<LazyValue>(&visible) {
+            Ok(visible) => self.write_field(dest, nested, key, &visible, colors),
+            Err(_) => self.write_field(dest, nested, key, value, colors),
+        }
+    }
+
+    /// Writes a single ` key=value` pair
+    fn write_field(
+        &self,
+        dest: &mut String,
+        nested: &mut String,
+        key: &str,
+        value: &LazyValue,
+        colors: &FieldColors,
+    ) {
+        let is_nested = value.is_object() || value.is_array();
+        if is_nested && self.nested == NestedStyle::Pretty {
+            write_pretty(nested, key, value, colors);
+            return;
+        }
+
+        dest.push(' ');
+        dest.push_str(colors.key);
+        dest.push_str(key);
+        dest.push('=');
+        dest.push_str(colors.value);
+        match value.as_str() {
+            Some(s) if !needs_quotes(s) => dest.push_str(s),
+            _ => dest.push_str(value.as_raw_str()),
+        }
+    }
+
+    /// Checks if a top-level key has been hidden
+    fn is_hidden(&self, key: &str) -> bool {
+        self.hidden.iter().any(|path| match path.segments() {
+            [segment] => segment == key,
+            // a flattened key, like '"err.stack": "..."'
+            segments => key.split('.').eq(segments.iter().map(String::as_str)),
+        })
+    }
+}
+
+/// The ANSI color codes used for extra fields, empty when not using colors
+pub(crate) struct FieldColors {
+    pub key: &'static str,
+    pub value: &'static str,
+    pub reset: &'static str,
+}
+
+/// Checks if a top-level key is one of the fields used by the field map
+fn is_used(key: &str, fields: &FieldMap) -> bool {
+    [
+        fields.timestamp(),
+        fields.level(),
+        fields.message(),
+        fields.stack(),
+    ]
+    .into_iter()
+    .flatten()
+    .any(|path| match path.segments() {
+        [segment] => segment == key,
+        // a flattened key, like '"log.level": "info"' in ECS logs
+        segments => key.split('.').eq(segments.iter().map(String::as_str)),
+    })
+}
+
+/// Writes the JSON object `value` to `dest` without the fields at the paths
+/// `hidden` (relative to it), keeping the order and text of the rest
+fn write_without(dest: &mut String, value: &LazyValue, hidden: &[&[String]]) {
+    dest.push('{');
+    let mut first = true;
+    for entry in sonic_rs::to_object_iter(value.as_raw_str()) {
+        let Ok((key, value)) = entry else {
+            break;
+        };
+        let within = hidden
+            .iter()
+            .filter_map(|path| match path.split_first() {
+                Some((first, rest)) if *first == *key => Some(rest),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if within.iter().any(|rest| rest.is_empty()) {
+            continue;
+        }
+        if !first {
+            dest.push(',');
+        }
+        first = false;
+        dest.push_str(&sonic_rs::to_string(&*key).unwrap_or_default());
+        dest.push(':');
+        match value.is_object() && !within.is_empty() {
+            true => write_without(dest, &value, &within),
+            false => dest.push_str(value.as_raw_str()),
+        }
+    }
+    dest.push('}');
+}
+
+/// Checks if a string value must be quoted to be read back unambiguously
+fn needs_quotes(s: &str) -> bool {
+    s.is_empty() || s.contains(|c: char| c.is_whitespace() || c == '"' || c == '=')
+}
+
+/// Writes a nested value as indented, pretty-printed JSON, on its own lines
+fn write_pretty(dest: &mut String, key: &str, value: &LazyValue, colors: &FieldColors) {
+    let pretty = sonic_rs::from_str::<sonic_rs::Value>(value.as_raw_str())
+        .and_then(|value| sonic_rs::to_string_pretty(&value))
+        .map_or(Cow::Borrowed(value.as_raw_str()), Cow::Owned);
+
+    write!(dest, "    {}{key}={}", colors.key, colors.value).unwrap();
+    for (i, line) in pretty.lines().enumerate() {
+        if i > 0 {
+            dest.push_str("    ");
+        }
+        dest.push_str(line);
+        dest.push_str(colors.reset);
+        dest.push('\n');
+    }
+}
+
+// --------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fields::paths;
+
+    const NO_COLORS: FieldColors = FieldColors {
+        key: "",
+        value: "",
+        reset: "",
+    };
+
+    fn render(extra: &ExtraFields, line: &str) -> (String, String) {
+        let mut dest = String::new();
+        let mut nested = String::new();
+        extra.write(
+            &mut dest,
+            &mut nested,
+            line,
+            &FieldMap::default(),
+            &NO_COLORS,
+        );
+        (dest, nested)
+    }
+
+    const LINE: &str = r#"{"level":"info","message":"hi","requestId":"abc","durationMs":12,"user":{"id":1,"name":"A B"},"note":"two words"}"#;
+
+    #[test]
+    fn test_all_fields() {
+        let (dest, nested) = render(&ExtraFields::all(), LINE);
+        assert_eq!(
+            dest,
+            r#" requestId=abc durationMs=12 user={"id":1,"name":"A B"} note="two words""#
+        );
+        assert_eq!(nested, "");
+    }
+
+    #[test]
+    fn test_hidden_fields() {
+        let extra = ExtraFields::all().with_hidden(paths(&["user", "note"]));
+        let (dest, _) = render(&extra, LINE);
+        assert_eq!(dest, " requestId=abc durationMs=12");
+    }
+
+    #[test]
+    fn test_hidden_nested_fields() {
+        let line = r#"{"message":"hi","err":{"type":"Error","stack":"at a\nat b","cause":{"code":1}},"err.stack":"flat"}"#;
+        let extra =
+            ExtraFields::all().with_hidden(paths(&["err.stack", "err.cause.code", "err.missing"]));
+        let (dest, _) = render(&extra, line);
+        assert_eq!(dest, r#" err={"type":"Error","cause":{}}"#);
+
+        // within the fields asked for too
+        let extra = ExtraFields::only(paths(&["err"])).with_hidden(paths(&["err.cause"]));
+        let (dest, _) = render(&extra, line);
+        assert_eq!(dest, r#" err={"type":"Error","stack":"at a\nat b"}"#);
+
+        // and in the pretty style
+        let extra = ExtraFields::only(paths(&["err"]))
+            .with_hidden(paths(&["err.stack", "err.type"]))
+            .with_nested(NestedStyle::Pretty);
+        let (_, nested) = render(&extra, line);
+        assert_eq!(
+            nested,
+            "    err={\n      \"cause\": {\n        \"code\": 1\n      }\n    }\n"
+        );
+    }
+
+    #[test]
+    fn test_only_fields() {
+        let extra = ExtraFields::only(paths(&["durationMs", "user.name", "missing"]));
+        let (dest, _) = render(&extra, LINE);
+        assert_eq!(dest, r#" durationMs=12 user.name="A B""#);
+    }
+
+    #[test]
+    fn test_pretty_nested_fields() {
+        let extra =
+            ExtraFields::only(paths(&["requestId", "user"])).with_nested(NestedStyle::Pretty);
+        let (dest, nested) = render(&extra, LINE);
+        assert_eq!(dest, " requestId=abc");
+        assert_eq!(
+            nested,
+            "    user={\n      \"id\": 1,\n      \"name\": \"A B\"\n    }\n"
+        );
+    }
+
+    #[test]
+    fn test_flattened_used_fields() {
+        let fields = FieldMap::default().with_level(paths(&["log.level"]));
+        assert!(is_used("log.level", &fields));
+        assert!(is_used("message", &fields));
+        assert!(!is_used("log", &fields));
+    }
+}
@@ -0,0 +1,258 @@
+use std::{borrow::Cow, str::FromStr};
+
+use sonic_rs::{JsonValueTrait, LazyValue};
+
+// --------------------------------------------------------------------------
+
+/// A path to a (possibly nested) field in a JSON object, i.e. `log.level`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldPath {
+    segments: Vec<String>,
+}
+
+impl FieldPath {
+    /// Creates a new field path from a dotted string, i.e. `log.level`
+    pub fn new(path: &str) -> Result<Self, FieldPathError> {
+        if path.is_empty() {
+            return Err(FieldPathError::Empty);
+        }
+        let segments = path.split('.').map(str::to_string).collect::<Vec<_>>();
+        if segments.iter().any(String::is_empty) {
+            return Err(FieldPathError::EmptySegment(path.to_string()));
+        }
+        Ok(Self { segments })
+    }
+
+    /// The individual keys of the path
+    pub fn segments(&self) -> &[String] {
+        &self.segments
+    }
+
+    /// Looks up the value at this path in the given JSON object.
+    ///
+    /// Some loggers emit flattened keys with dots in them (`"log.level"`), so
+    /// if a nested path is not found we also try the whole path as a key.
+    ///
+    pub fn lookup<'a>(&self, json: &'a str) -> Option<LazyValue<'a>> {
+        match sonic_rs::get_from_str(json, &self.segments) {
+            Ok(value) => Some(value),
+            Err(_) if self.segments.len() > 1 => {
+                sonic_rs::get_from_str(json, [self.segments.join(".").as_str()]).ok()
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// Looks up the value at this path in the JSON object `json`, given one of
+    /// its top-level fields, i.e. while iterating over them. Nested paths
+    /// starting at the field are looked up in the whole object.
+    pub(crate) fn lookup_field<'a>(
+        &self,
+        json: &'a str,
+        key: &str,
+        value: &LazyValue<'a>,
+    ) -> Option<LazyValue<'a>> {
+        match self.segments.as_slice() {
+            [name] if name == key => Some(value.clone()),
+            [first, ..] if first == key => sonic_rs::get_from_str(json, &self.segments).ok(),
+            // a flattened key
+            [_, _, ..] if key.split('.').eq(&self.segments) => Some(value.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// The text of a JSON value for matching against, the unescaped contents of
+/// strings and the JSON text of anything else (i.e. `404` or `true`)
+pub(crate) fn value_text<'a>(value: &'a LazyValue) -> Cow<'a, str> {
+    match value.as_str() {
+        Some(s) => Cow::Borrowed(s),
+        None if value.is_str() => sonic_rs::from_str::<String>(value.as_raw_str())
+            .map(Cow::Owned)
+            .unwrap_or(Cow::Borrowed(value.as_raw_str())),
+        None => Cow::Borrowed(value.as_raw_str()),
+    }
+}
+
+impl FromStr for FieldPath {
+    type Err = FieldPathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+impl std::fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.segments.join("."))
+    }
+}
+
+/// Errors that can happen when parsing a field path
+#[derive(Debug)]
+pub enum FieldPathError {
+    /// The path was empty
+    Empty,
+    /// The path contained an empty key, i.e. `log..level`
+    EmptySegment(String),
+}
+
+impl std::fmt::Display for FieldPathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "field path is empty"),
+            Self::EmptySegment(path) => write!(f, "field path '{path}' has an empty key"),
+        }
+    }
+}
+
+impl std::error::Error for FieldPathError {}
+
+// --------------------------------------------------------------------------
+
+/// Maps each of the logical fields of a log line to one or more JSON paths.
+///
+/// The paths for each field are tried in order, and the first one found in a
+/// line is used.
+///
+#[derive(Debug, Clone)]
+pub struct FieldMap {
+    timestamp: Vec<FieldPath>,
+    level: Vec<FieldPath>,
+    message: Vec<FieldPath>,
+    stack: Vec<FieldPath>,
+}
+
+impl FieldMap {
+    /// Creates a new field map from the given lists of paths, without any
+    /// paths for stack traces
+    pub fn new(timestamp: Vec<FieldPath>, level: Vec<FieldPath>, message: Vec<FieldPath>) -> Self {
+        Self {
+            timestamp,
+            level,
+            message,
+            stack: Vec::new(),
+        }
+    }
+
+    /// Creates a field map with no paths at all, useful as a base for
+    /// overriding some of the fields of a schema
+    pub fn empty() -> Self {
+        Self::new(Vec::new(), Vec::new(), Vec::new())
+    }
+
+    /// Returns a copy of `base`, with the lists of paths that are not empty
+    /// in this map replacing those of `base`
+    pub fn overlay(&self, base: &FieldMap) -> FieldMap {
+        let pick = |over: &Vec<FieldPath>, base: &Vec<FieldPath>| {
+            if over.is_empty() { base } else { over }.clone()
+        };
+        FieldMap::new(
+            pick(&self.timestamp, &base.timestamp),
+            pick(&self.level, &base.level),
+            pick(&self.message, &base.message),
+        )
+        .with_stack(pick(&self.stack, &base.stack))
+    }
+
+    /// Replaces the paths tried for the timestamp
+    pub fn with_timestamp(mut self, paths: Vec<FieldPath>) -> Self {
+        self.timestamp = paths;
+        self
+    }
+
+    /// Replaces the paths tried for the level
+    pub fn with_level(mut self, paths: Vec<FieldPath>) -> Self {
+        self.level = paths;
+        self
+    }
+
+    /// Replaces the paths tried for the message
+    pub fn with_message(mut self, paths: Vec<FieldPath>) -> Self {
+        self.message = paths;
+        self
+    }
+
+    /// Replaces the paths tried for the stack trace
+    pub fn with_stack(mut self, paths: Vec<FieldPath>) -> Self {
+        self.stack = paths;
+        self
+    }
+
+    pub fn timestamp(&self) -> &[FieldPath] {
+        &self.timestamp
+    }
+    pub fn level(&self) -> &[FieldPath] {
+        &self.level
+    }
+    pub fn message(&self) -> &[FieldPath] {
+        &self.message
+    }
+    pub fn stack(&self) -> &[FieldPath] {
+        &self.stack
+    }
+}
+
+impl Default for FieldMap {
+    /// The field names used by winston
+    fn default() -> Self {
+        Self::new(
+            paths(&["timestamp"]),
+            paths(&["level"]),
+            paths(&["message"]),
+        )
+        .with_stack(paths(&["stack"]))
+    }
+}
+
+/// Helper to build a list of paths from static strings
+pub(crate) fn paths(names: &[&str]) -> Vec<FieldPath> {
+    names
+        .iter()
+        .map(|name| FieldPath::new(name).expect("static field path is valid"))
+        .collect()
+}
+
+// --------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_path() {
+        let path: FieldPath = "log.level".parse().unwrap();
+        assert_eq!(path.segments(), &["log", "level"]);
+        assert_eq!(path.to_string(), "log.level");
+    }
+
+    #[test]
+    fn test_parse_invalid_path() {
+        assert!(FieldPath::new("").is_err());
+        assert!(FieldPath::new("log..level").is_err());
+        assert!(FieldPath::new(".level").is_err());
+    }
+
+    #[test]
+    fn test_overlay() {
+        let overrides = FieldMap::empty().with_message(paths(&["msg"]));
+        let fields = overrides.overlay(&FieldMap::default());
+        assert_eq!(fields.timestamp(), paths(&["timestamp"]));
+        assert_eq!(fields.message(), paths(&["msg"]));
+    }
+
+    #[test]
+    fn test_lookup_nested() {
+        let json = r#"{"log":{"level":"warn"},"msg":"hi"}"#;
+        let path = FieldPath::new("log.level").unwrap();
+        assert_eq!(path.lookup(json).unwrap().as_raw_str(), r#""warn""#);
+        assert!(FieldPath::new("log.logger").unwrap().lookup(json).is_none());
+    }
+
+    #[test]
+    fn test_lookup_flattened_key() {
+        let json = r#"{"log.level":"warn","msg":"hi"}"#;
+        let path = FieldPath::new("log.level").unwrap();
+        assert_eq!(path.lookup(json).unwrap().as_raw_str(), r#""warn""#);
+    }
+}
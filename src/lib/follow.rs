@@ -0,0 +1,230 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+/// How long to wait before checking a file for new data again
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Reads a file that is being appended to, like `tail -F`.
+///
+/// At the end of the file, reads block until new data is appended instead of
+/// returning 0. When the file is truncated it is read again from the start,
+/// and when it is replaced (i.e. by log rotation) the new file is opened, once
+/// the old one has been read to the end.
+///
+pub struct FollowReader {
+    path: PathBuf,
+    file: File,
+    pos: u64,
+    poll_interval: Duration,
+}
+
+impl FollowReader {
+    /// Opens the file at `path` for following, starting at the beginning
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let file = File::open(&path)?;
+        Ok(Self {
+            path,
+            file,
+            pos: 0,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        })
+    }
+
+    /// Start reading at the last `lines` lines of the file, instead of at
+    /// the beginning
+    pub fn with_last_lines(mut self, lines: usize) -> io::Result<Self> {
+        self.pos = tail_offset(&mut self.file, lines)?;
+        self.file.seek(SeekFrom::Start(self.pos))?;
+        Ok(self)
+    }
+
+    /// Sets how long to wait before checking the file for new data again
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Checks if the file was truncated or replaced since the last read, and
+    /// starts over with the (new) file if so. Returns `true` if we did.
+    fn reopen_if_changed(&mut self) -> io::Result<bool> {
+        let metadata = match std::fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            // mid rotation, the new file is not there yet
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err),
+        };
+
+        if !is_same_file(&self.file.metadata()?, &metadata) {
+            self.file = File::open(&self.path)?;
+            self.pos = 0;
+            return Ok(true);
+        }
+        if metadata.len() < self.pos {
+            self.pos = self.file.seek(SeekFrom::Start(0))?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+impl Read for FollowReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.file.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                self.pos += n as u64;
+                return Ok(n);
+            }
+            if !self.reopen_if_changed()? {
+                thread::sleep(self.poll_interval);
+            }
+        }
+    }
+}
+
+/// Checks if two sets of metadata belong to the same file. Without inodes to
+/// compare, we can only detect truncation, not replacement.
+#[cfg(unix)]
+fn is_same_file(a: &std::fs::Metadata, b: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_a: &std::fs::Metadata, _b: &std::fs::Metadata) -> bool {
+    true
+}
+
+/// Finds the offset of the start of the last `lines` lines of a file, by
+/// scanning backwards from the end for newlines
+fn tail_offset(file: &mut File, lines: usize) -> io::Result<u64> {
+    const CHUNK_SIZE: u64 = 8192;
+
+    let len = file.seek(SeekFrom::End(0))?;
+    if lines == 0 {
+        return Ok(len);
+    }
+    let mut chunk = vec![0; CHUNK_SIZE as usize];
+    let mut end = len;
+    let mut newlines = 0;
+    // a newline ending the last line does not start a new one
+    let mut skip_last = true;
+
+    while end > 0 {
+        let start = end.saturating_sub(CHUNK_SIZE);
+        let chunk = &mut chunk[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+
+        for (i, byte) in chunk.iter().enumerate().rev() {
+            if *byte != b'\n' {
+                skip_last = false;
+                continue;
+            }
+            if skip_last {
+                skip_last = false;
+                continue;
+            }
+            newlines += 1;
+            if newlines == lines {
+                return Ok(start + i as u64 + 1);
+            }
+        }
+        end = start;
+    }
+
+    Ok(0)
+}
+
+// --------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+
+    use super::*;
+
+    /// A file in the temp dir, removed again when dropped
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, content: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("jl-pretty-{}-{name}", std::process::id()));
+            std::fs::write(&path, content).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn read_line(reader: &mut BufReader<FollowReader>) -> String {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        line
+    }
+
+    #[test]
+    fn test_tail_offset() {
+        let tmp = TempFile::new("tail", "one\ntwo\nthree\n");
+        let mut file = File::open(&tmp.0).unwrap();
+        assert_eq!(tail_offset(&mut file, 0).unwrap(), 14);
+        assert_eq!(tail_offset(&mut file, 1).unwrap(), 8);
+        assert_eq!(tail_offset(&mut file, 2).unwrap(), 4);
+        assert_eq!(tail_offset(&mut file, 3).unwrap(), 0);
+        assert_eq!(tail_offset(&mut file, 10).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_follow_last_lines_and_appends() {
+        let tmp = TempFile::new("append", "one\ntwo\n");
+        let reader = FollowReader::open(&tmp.0)
+            .and_then(|reader| reader.with_last_lines(1))
+            .unwrap();
+        let mut reader = BufReader::new(reader.with_poll_interval(Duration::from_millis(1)));
+        assert_eq!(read_line(&mut reader), "two\n");
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&tmp.0)
+            .unwrap();
+        file.write_all(b"three\n").unwrap();
+        assert_eq!(read_line(&mut reader), "three\n");
+    }
+
+    #[test]
+    fn test_follow_truncation() {
+        let tmp = TempFile::new("truncate", "one\ntwo\n");
+        let reader = FollowReader::open(&tmp.0).unwrap();
+        let mut reader = BufReader::new(reader.with_poll_interval(Duration::from_millis(1)));
+        assert_eq!(read_line(&mut reader), "one\n");
+        assert_eq!(read_line(&mut reader), "two\n");
+
+        std::fs::write(&tmp.0, "new\n").unwrap();
+        assert_eq!(read_line(&mut reader), "new\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_rotation() {
+        let tmp = TempFile::new("rotate", "one\n");
+        let rotated = TempFile::new("rotate.1", "");
+        let reader = FollowReader::open(&tmp.0).unwrap();
+        let mut reader = BufReader::new(reader.with_poll_interval(Duration::from_millis(1)));
+        assert_eq!(read_line(&mut reader), "one\n");
+
+        std::fs::rename(&tmp.0, &rotated.0).unwrap();
+        std::fs::write(&tmp.0, "first of the new file\n").unwrap();
+        assert_eq!(read_line(&mut reader), "first of the new file\n");
+    }
+}
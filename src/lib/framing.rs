@@ -0,0 +1,486 @@
+use std::{
+    io::{self, BufRead, Cursor, Read},
+    iter::Enumerate,
+};
+
+use crate::{decompress::Peeked, prefix::has_known_prefix};
+
+// --------------------------------------------------------------------------
+
+/// How the input is split into records
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Framing {
+    /// One JSON object per line (the fast path)
+    #[default]
+    Lines,
+    /// Complete top-level JSON values, however they are spread over lines,
+    /// i.e. pretty-printed objects or objects with nothing between them
+    Json,
+}
+
+impl Framing {
+    /// Splits the input into records, each along with the position it starts
+    /// at. Input starting with an array of objects (i.e. '[{') is
+    /// taken to be an array of records, whatever the framing, and its
+    /// elements are read one by one.
+    pub(crate) fn records<R: BufRead>(self, mut reader: R) -> Records<Peeked<R>> {
+        let mut peeked = Vec::new();
+        let array = match starts_with_array(&mut reader, &mut peeked) {
+            true => Array::Start,
+            false => Array::Outside,
+        };
+        let reader = Cursor::new(peeked).chain(reader);
+        match (self, array) {
+            (Framing::Lines, Array::Outside) => Records::Lines(reader.lines().enumerate()),
+            _ => Records::Values(JsonValues {
+                reader,
+                pos: Position::default(),
+                array,
+            }),
+        }
+    }
+}
+
+/// Where a record starts in the input, as the index of its line and the index
+/// of its first byte within that line
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Position {
+    pub line_idx: usize,
+    pub column_idx: usize,
+}
+
+impl Position {
+    /// The start of the line with the given index
+    pub(crate) fn line(line_idx: usize) -> Self {
+        Self {
+            line_idx,
+            column_idx: 0,
+        }
+    }
+
+    /// The same position, in an input that has `count` more lines before it
+    pub(crate) fn after_lines(self, count: usize) -> Self {
+        Self {
+            line_idx: self.line_idx + count,
+            ..self
+        }
+    }
+
+    /// The position `count` bytes further along the same line
+    pub(crate) fn after_columns(self, count: usize) -> Self {
+        Self {
+            column_idx: self.column_idx + count,
+            ..self
+        }
+    }
+
+    /// Moves the position past `bytes`
+    fn advance(&mut self, bytes: &[u8]) {
+        match bytes.iter().rposition(|b| *b == b'\n') {
+            Some(last) => {
+                self.line_idx += count_newlines(bytes);
+                self.column_idx = bytes.len() - last - 1;
+            }
+            None => self.column_idx += bytes.len(),
+        }
+    }
+}
+
+/// The records of an input, along with the position they start at
+pub(crate) enum Records<R> {
+    Lines(Enumerate<io::Lines<R>>),
+    Values(JsonValues<R>),
+}
+
+impl<R: BufRead> Iterator for Records<R> {
+    type Item = (Position, io::Result<String>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Records::Lines(lines) => lines
+                .next()
+                .map(|(line_idx, line)| (Position::line(line_idx), line)),
+            Records::Values(values) => values.next(),
+        }
+    }
+}
+
+// --------------------------------------------------------------------------
+
+/// Where we are in relation to a top-level array of records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Array {
+    /// Before the '[' of the array
+    Start,
+    /// Among the elements of the array
+    Elements,
+    /// Not in an array, the values are records of their own
+    Outside,
+}
+
+/// Splits the input on complete top-level JSON values (or the elements of a
+/// top-level array), without parsing them. Only strings and brackets are
+/// tracked, so malformed values are passed on as they are, to fail when
+/// parsed (with the position of the error).
+pub(crate) struct JsonValues<R> {
+    reader: R,
+    pos: Position,
+    array: Array,
+}
+
+impl<R: BufRead> JsonValues<R> {
+    /// Skips the whitespace before the next value, and returns its first byte
+    /// (without consuming it), or `None` if the input ends first
+    fn skip_whitespace(&mut self) -> io::Result<Option<u8>> {
+        loop {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            let n = buf.iter().take_while(|b| is_whitespace(**b)).count();
+            self.pos.advance(&buf[..n]);
+            let next = buf.get(n).copied();
+            self.reader.consume(n);
+            if next.is_some() {
+                return Ok(next);
+            }
+        }
+    }
+
+    /// Skips to the start of the next record, past the brackets and commas
+    /// of an array, and returns `false` if the input ends first
+    fn skip_to_record(&mut self) -> io::Result<bool> {
+        loop {
+            let Some(next) = self.skip_whitespace()? else {
+                return Ok(false);
+            };
+            match (self.array, next) {
+                (Array::Start, _) => self.array = Array::Elements,
+                (Array::Elements, b',') => {}
+                (Array::Elements, b']') => self.array = Array::Outside,
+                _ => return Ok(true),
+            }
+            self.pos.column_idx += 1;
+            self.reader.consume(1);
+        }
+    }
+
+    /// Reads the value starting at the current position into `value`. An
+    /// unterminated value ends with the input. Outside of arrays, anything not
+    /// starting like an object, array or string (i.e. a line of plain text)
+    /// runs to the end of its line, so it makes a single (invalid) record.
+    fn read_value(&mut self, value: &mut Vec<u8>) -> io::Result<()> {
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+        loop {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(());
+            }
+            let is_scalar = value
+                .first()
+                .or(buf.first())
+                .is_some_and(|first| !matches!(first, b'{' | b'[' | b'"' | b'}' | b']'));
+            let is_text = is_scalar && self.array == Array::Outside;
+
+            let mut end = None;
+            for (i, &b) in buf.iter().enumerate() {
+                if in_string {
+                    match b {
+                        _ if escaped => escaped = false,
+                        b'\\' => escaped = true,
+                        b'"' => {
+                            in_string = false;
+                            if depth == 0 {
+                                end = Some(i + 1);
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+                if is_text {
+                    if b == b'\n' {
+                        end = Some(i);
+                        break;
+                    }
+                    continue;
+                }
+                // scalars in arrays (i.e. numbers) run until whatever comes
+                // after them
+                if is_scalar {
+                    if is_whitespace(b) || matches!(b, b'{' | b'}' | b'[' | b']' | b'"' | b',') {
+                        end = Some(i);
+                        break;
+                    }
+                    continue;
+                }
+                match b {
+                    b'"' => in_string = true,
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth = depth.saturating_sub(1);
+                        if depth == 0 {
+                            end = Some(i + 1);
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            let n = end.unwrap_or(buf.len());
+            self.pos.advance(&buf[..n]);
+            value.extend_from_slice(&buf[..n]);
+            self.reader.consume(n);
+            if end.is_some() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for JsonValues<R> {
+    type Item = (Position, io::Result<String>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.skip_to_record() {
+            Ok(true) => {}
+            Ok(false) => return None,
+            Err(err) => return Some((self.pos, Err(err))),
+        }
+        let start = self.pos;
+        let mut value = Vec::new();
+        let value = self.read_value(&mut value).and_then(|_| {
+            String::from_utf8(value).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+        });
+        Some((start, value))
+    }
+}
+
+// How much of the first line is read (at most) to tell an array from a prefix
+// in brackets
+const ARRAY_LINE_PEEK: u64 = 4096;
+
+/// Checks if the input starts with an array of objects (or an empty array).
+/// The bytes read to find out are put in `peeked`, to be read again. Lines
+/// starting with a prefix in brackets (i.e. kubectl's '[pod/api/api] {...}')
+/// or a banner like '[INFO] booting' are not arrays. Read errors are left for
+/// later.
+fn starts_with_array<R: BufRead>(reader: &mut R, peeked: &mut Vec<u8>) -> bool {
+    if peek_past_whitespace(reader, peeked) != Some(b'[') {
+        return false;
+    }
+    let line_start = peeked.len();
+    peeked.push(b'[');
+    reader.consume(1);
+    if !matches!(peek_past_whitespace(reader, peeked), Some(b'{' | b']')) {
+        return false;
+    }
+    // the rest of the line (or enough of it) for checking for prefixes
+    if reader
+        .take(ARRAY_LINE_PEEK)
+        .read_until(b'\n', peeked)
+        .is_err()
+    {
+        return false;
+    }
+    !has_known_prefix(&String::from_utf8_lossy(&peeked[line_start..]))
+}
+
+/// Reads the whitespace at the start of `reader` into `peeked`, however many
+/// reads it takes, and returns the byte after it (without consuming it), or
+/// `None` if the input ends (or fails) first
+fn peek_past_whitespace<R: BufRead>(reader: &mut R, peeked: &mut Vec<u8>) -> Option<u8> {
+    loop {
+        let buf = reader.fill_buf().ok()?;
+        if buf.is_empty() {
+            return None;
+        }
+        let n = buf.iter().take_while(|b| is_whitespace(**b)).count();
+        peeked.extend_from_slice(&buf[..n]);
+        let next = buf.get(n).copied();
+        reader.consume(n);
+        if next.is_some() {
+            return next;
+        }
+    }
+}
+
+fn is_whitespace(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b'\r')
+}
+
+fn count_newlines(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|b| **b == b'\n').count()
+}
+
+// --------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Cursor};
+
+    use super::*;
+
+    fn frame(input: &str) -> Vec<(usize, String)> {
+        // a tiny buffer, so values are split over several reads
+        frame_with(Framing::Json, 3, input)
+    }
+
+    fn frame_with(framing: Framing, capacity: usize, input: &str) -> Vec<(usize, String)> {
+        positions(framing, capacity, input)
+            .into_iter()
+            .map(|(pos, value)| (pos.line_idx, value))
+            .collect()
+    }
+
+    fn positions(framing: Framing, capacity: usize, input: &str) -> Vec<(Position, String)> {
+        let reader = BufReader::with_capacity(capacity, Cursor::new(input.to_string()));
+        framing
+            .records(reader)
+            .map(|(pos, value)| (pos, value.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_frame_json_values() {
+        let input = concat!(
+            "{\n",
+            "  \"message\": \"a } in a \\\"string\\\"\",\n",
+            "  \"nested\": {\"list\": [1, 2]}\n",
+            "}\n",
+            "\n",
+            "{\"a\":1}{\"b\":2} \"text\"\n",
+            "not json {\"a\":1}\r\n",
+        );
+        assert_eq!(
+            frame(input),
+            [
+                (
+                    0,
+                    concat!(
+                        "{\n",
+                        "  \"message\": \"a } in a \\\"string\\\"\",\n",
+                        "  \"nested\": {\"list\": [1, 2]}\n",
+                        "}"
+                    )
+                    .to_string()
+                ),
+                (5, r#"{"a":1}"#.to_string()),
+                (5, r#"{"b":2}"#.to_string()),
+                (5, r#""text""#.to_string()),
+                (6, "not json {\"a\":1}\r".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_frame_positions() {
+        let at = |line_idx, column_idx| Position {
+            line_idx,
+            column_idx,
+        };
+        let starts: Vec<_> = positions(Framing::Json, 3, "{}  {\n}\n  [1,\n2] 42")
+            .into_iter()
+            .map(|(pos, _)| pos)
+            .collect();
+        assert_eq!(starts, [at(0, 0), at(0, 4), at(2, 2), at(3, 3)]);
+
+        let starts: Vec<_> = positions(Framing::Lines, 16, "[\n {},{\"a\":\n1}, {}]")
+            .into_iter()
+            .map(|(pos, _)| pos)
+            .collect();
+        assert_eq!(starts, [at(1, 1), at(1, 4), at(2, 4)]);
+    }
+
+    #[test]
+    fn test_frame_malformed_values() {
+        // stray brackets and unterminated values come through, to fail when
+        // they are parsed
+        assert_eq!(
+            frame("}\n{\"a\":\n  [1, 2}\n"),
+            [(0, "}".to_string()), (1, "{\"a\":\n  [1, 2}\n".to_string())]
+        );
+        let lines: Vec<_> = Framing::Lines
+            .records(Cursor::new("{}\n{\n"))
+            .map(|(pos, line)| (pos.line_idx, line.unwrap()))
+            .collect();
+        assert_eq!(lines, [(0, "{}".to_string()), (1, "{".to_string())]);
+    }
+
+    #[test]
+    fn test_frame_json_array() {
+        let input = concat!(
+            "\n[\n",
+            "  {\"message\": \"first\", \"tags\": [\"a\", \"b\"]},\n",
+            "  {\"message\": \"second\"},42,\n",
+            "  [1, 2]\n",
+            "]\n",
+            "{\"message\": \"after\"}\n",
+        );
+        let expected = [
+            (2, r#"{"message": "first", "tags": ["a", "b"]}"#.to_string()),
+            (3, r#"{"message": "second"}"#.to_string()),
+            (3, "42".to_string()),
+            (4, "[1, 2]".to_string()),
+            (6, r#"{"message": "after"}"#.to_string()),
+        ];
+        assert_eq!(frame_with(Framing::Json, 16, input), expected);
+        // however many reads it takes to find the start of the array
+        assert_eq!(frame_with(Framing::Json, 1, input), expected);
+
+        // arrays are found whatever the framing
+        assert_eq!(frame_with(Framing::Lines, 16, input), expected);
+        assert_eq!(frame("[]"), []);
+    }
+
+    #[test]
+    fn test_frame_indented_json_array_after_decompression() {
+        // the reader puts back the bytes it reads to detect the compression,
+        // and those fill the first read
+        let input = "[\n    {\"message\": \"first\"},\n    {\"message\": \"second\"}\n]\n";
+        let reader = crate::DecompressReader::new(Cursor::new(input)).unwrap();
+        let values: Vec<_> = Framing::Lines
+            .records(reader)
+            .map(|(pos, value)| (pos.line_idx, value.unwrap()))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                (1, r#"{"message": "first"}"#.to_string()),
+                (2, r#"{"message": "second"}"#.to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_frame_lines_starting_with_brackets() {
+        // kubectl prefixes and banners are not arrays
+        let input = concat!(
+            r#"[pod/api-7d9f/api] {"message":"first"}"#,
+            "\n",
+            r#"[pod/api-7d9f/api] {"message":"second"}"#,
+            "\n",
+        );
+        assert_eq!(
+            frame_with(Framing::Lines, 8192, input),
+            [
+                (0, r#"[pod/api-7d9f/api] {"message":"first"}"#.to_string()),
+                (1, r#"[pod/api-7d9f/api] {"message":"second"}"#.to_string()),
+            ]
+        );
+        assert_eq!(
+            frame_with(Framing::Lines, 8192, "[INFO] booting\n{}\n"),
+            [(0, "[INFO] booting".to_string()), (1, "{}".to_string())]
+        );
+        // a kubectl prefix around something that looks like an object
+        let input = r#"[{pod}] {"message":"first"}"#;
+        assert_eq!(
+            frame_with(Framing::Lines, 8192, input),
+            [(0, input.to_string())]
+        );
+    }
+}
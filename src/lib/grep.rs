@@ -0,0 +1,336 @@
+use std::{collections::VecDeque, io};
+
+use regex::Regex;
+
+use crate::{
+    fields::{FieldPath, value_text},
+    logline::LogLine,
+};
+
+// --------------------------------------------------------------------------
+
+/// Selects log lines by regular expressions on their message and fields, like
+/// grep does on plain text, optionally with some of the lines around them
+#[derive(Debug, Clone, Default)]
+pub struct Grep {
+    message: Option<Regex>,
+    fields: Vec<(FieldPath, Regex)>,
+    before: usize,
+    after: usize,
+}
+
+impl Grep {
+    /// Select lines with a message matching `rx`
+    pub fn with_message(mut self, rx: Regex) -> Self {
+        self.message = Some(rx);
+        self
+    }
+
+    /// Select lines with a field at `path` matching `rx`. Fields that are not
+    /// strings are matched as JSON text, i.e. `404` or `true`.
+    pub fn with_field(mut self, path: FieldPath, rx: Regex) -> Self {
+        self.fields.push((path, rx));
+        self
+    }
+
+    /// Also show `before` lines before and `after` lines after each selected
+    /// line
+    pub fn with_context(mut self, before: usize, after: usize) -> Self {
+        self.before = before;
+        self.after = after;
+        self
+    }
+
+    /// Returns `true` if there is nothing to match, and so all lines match
+    pub fn is_empty(&self) -> bool {
+        self.message.is_none() && self.fields.is_empty()
+    }
+
+    /// The expression the messages are matched against, if any
+    pub(crate) fn message(&self) -> Option<&Regex> {
+        self.message.as_ref()
+    }
+
+    /// Checks if a line matches all of the expressions
+    pub(crate) fn matches(&self, line: &LogLine) -> bool {
+        let message_matches = self
+            .message
+            .as_ref()
+            .is_none_or(|rx| rx.is_match(line.message()));
+        message_matches
+            && self.fields.iter().all(|(path, rx)| {
+                path.lookup(line.source())
+                    .is_some_and(|value| rx.is_match(&value_text(&value)))
+            })
+    }
+
+    /// Starts keeping track of the context of the selected lines, for a run
+    /// over some input
+    pub(crate) fn context(&self, separator: &'static str) -> Context {
+        Context {
+            before: self.before,
+            after: self.after,
+            held: VecDeque::with_capacity(self.before),
+            banner: None,
+            after_left: 0,
+            skipped: false,
+            shown_any: false,
+            last: Fate::Shown,
+            separator,
+        }
+    }
+}
+
+/// What becomes of a rendered line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Selection {
+    /// The line is left out by the other filters. It does not count as
+    /// context.
+    Hidden,
+    /// The line does not match, but is shown if close to a line that does
+    Context,
+    /// The line matches
+    Match,
+    /// The line is not a record of its own (i.e. an invalid line shown as
+    /// is), and goes wherever the last line seen by the grep went, so crash
+    /// output stays in context even if the line before it was filtered out
+    Continuation,
+}
+
+/// What became of the last line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fate {
+    Shown,
+    Held,
+    Dropped,
+}
+
+/// Writes the matching lines, and the lines around them. Like grep, groups of
+/// lines that are not adjacent are separated by a `--` line.
+///
+/// The banner of a session is written in front of the first line of the
+/// session that is shown, so sessions with no lines shown get no banner.
+///
+pub(crate) struct Context {
+    before: usize,
+    after: usize,
+    /// The last lines not shown, in case a matching line follows them, each
+    /// with the banner of the session it is the first line of (if it is)
+    held: VecDeque<(Option<String>, String)>,
+    /// The banner of the latest session, until a line of it is shown or held
+    banner: Option<String>,
+    /// The number of lines still to show after the last matching line
+    after_left: usize,
+    /// Set when a line has been left out since the last line shown
+    skipped: bool,
+    shown_any: bool,
+    last: Fate,
+    separator: &'static str,
+}
+
+impl Context {
+    /// Writes `rendered` to `w` if the line is to be shown now, and holds on
+    /// to it if it may be shown later. A `banner` that is not empty starts a
+    /// new session.
+    pub fn write<W: io::Write>(
+        &mut self,
+        selection: Selection,
+        banner: &str,
+        rendered: &str,
+        w: &mut W,
+    ) -> io::Result<()> {
+        if !banner.is_empty() {
+            self.banner = Some(banner.to_string());
+        }
+        match selection {
+            Selection::Hidden => Ok(()),
+            Selection::Continuation => match self.last {
+                Fate::Shown => {
+                    self.shown_any = true;
+                    self.write_shown(rendered, w)
+                }
+                Fate::Held => {
+                    if let Some((_, held)) = self.held.back_mut() {
+                        held.push_str(&self.banner.take().unwrap_or_default());
+                        held.push_str(rendered);
+                    }
+                    Ok(())
+                }
+                Fate::Dropped => Ok(()),
+            },
+            Selection::Match => {
+                let has_context = self.before > 0 || self.after > 0;
+                if has_context && self.shown_any && self.skipped {
+                    w.write_all(self.separator.as_bytes())?;
+                }
+                for (banner, line) in self.held.drain(..) {
+                    w.write_all(banner.unwrap_or_default().as_bytes())?;
+                    w.write_all(line.as_bytes())?;
+                }
+                self.skipped = false;
+                self.shown_any = true;
+                self.after_left = self.after;
+                self.last = Fate::Shown;
+                self.write_shown(rendered, w)
+            }
+            Selection::Context if self.after_left > 0 => {
+                self.after_left -= 1;
+                self.last = Fate::Shown;
+                self.write_shown(rendered, w)
+            }
+            Selection::Context => {
+                if self.held.len() == self.before {
+                    self.skipped = true;
+                    let Some((banner, _)) = self.held.pop_front() else {
+                        self.last = Fate::Dropped;
+                        return Ok(());
+                    };
+                    // the banner goes with the next line of its session
+                    if let Some(banner) = banner {
+                        match self.held.front_mut() {
+                            Some((next @ None, _)) => *next = Some(banner),
+                            Some(_) => {}
+                            None => {
+                                self.banner.get_or_insert(banner);
+                            }
+                        }
+                    }
+                }
+                self.held
+                    .push_back((self.banner.take(), rendered.to_string()));
+                self.last = Fate::Held;
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes a line that is shown now, after the banner of its session if
+    /// it is the first line of it shown
+    fn write_shown<W: io::Write>(&mut self, rendered: &str, w: &mut W) -> io::Result<()> {
+        if let Some(banner) = self.banner.take() {
+            w.write_all(banner.as_bytes())?;
+        }
+        w.write_all(rendered.as_bytes())
+    }
+}
+
+// --------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fields::FieldMap;
+
+    fn grep_lines(grep: &Grep, selections: &[Selection]) -> String {
+        let lines = selections.iter().map(|selection| (*selection, false));
+        grep_sessions(grep, &lines.collect::<Vec<_>>())
+    }
+
+    /// Like `grep_lines`, where lines may start a session with the banner
+    /// 'S<line no>'
+    fn grep_sessions(grep: &Grep, lines: &[(Selection, bool)]) -> String {
+        let mut context = grep.context("--\n");
+        let mut output = Vec::new();
+        for (i, (selection, starts_session)) in lines.iter().enumerate() {
+            let banner = match starts_session {
+                true => format!("S{}\n", i + 1),
+                false => String::new(),
+            };
+            context
+                .write(*selection, &banner, &format!("{}\n", i + 1), &mut output)
+                .unwrap();
+        }
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_grep_matches() {
+        let fields = FieldMap::default();
+        let line = r#"{"message":"Request failed","status":503,"user":{"name":"bob"}}"#;
+        let line = LogLine::parse(line, &fields).unwrap();
+
+        let rx = |s| Regex::new(s).unwrap();
+        let path = |s: &str| s.parse::<FieldPath>().unwrap();
+        assert!(Grep::default().matches(&line));
+        assert!(Grep::default().with_message(rx("fail")).matches(&line));
+        assert!(!Grep::default().with_message(rx("^fail")).matches(&line));
+        let grep = Grep::default()
+            .with_message(rx("fail"))
+            .with_field(path("status"), rx("^5\\d\\d$"))
+            .with_field(path("user.name"), rx("bob"));
+        assert!(grep.matches(&line));
+        let grep = grep.with_field(path("missing"), rx(""));
+        assert!(!grep.matches(&line));
+    }
+
+    #[test]
+    fn test_context_lines() {
+        use Selection::*;
+        let selections = [
+            Context, Context, Match, Context, Context, Context, Match, Context,
+        ];
+
+        let grep = Grep::default();
+        assert_eq!(grep_lines(&grep, &selections), "3\n7\n");
+
+        let grep = Grep::default().with_context(1, 1);
+        assert_eq!(grep_lines(&grep, &selections), "2\n3\n4\n--\n6\n7\n8\n");
+
+        // adjacent groups are not separated
+        let grep = Grep::default().with_context(2, 1);
+        assert_eq!(grep_lines(&grep, &selections), "1\n2\n3\n4\n5\n6\n7\n8\n");
+
+        // hidden lines are left out, and do not count as context
+        let selections = [Match, Hidden, Context, Context, Match];
+        let grep = Grep::default().with_context(0, 1);
+        assert_eq!(grep_lines(&grep, &selections), "1\n3\n--\n5\n");
+    }
+
+    #[test]
+    fn test_context_continuation_lines() {
+        use Selection::*;
+        let selections = [
+            Continuation,
+            Context,
+            Continuation,
+            Context,
+            Continuation,
+            Match,
+            Continuation,
+            Hidden,
+            Continuation,
+        ];
+
+        // hidden lines do not take the continuation lines after them along
+        let grep = Grep::default();
+        assert_eq!(grep_lines(&grep, &selections), "1\n6\n7\n9\n");
+
+        // continuation lines are held back along with their line
+        let grep = Grep::default().with_context(1, 0);
+        assert_eq!(grep_lines(&grep, &selections), "1\n--\n4\n5\n6\n7\n9\n");
+    }
+
+    #[test]
+    fn test_context_session_banners() {
+        use Selection::*;
+
+        // the banner goes before the first line of the session shown, and
+        // sessions without any lines shown get none
+        let lines = [
+            (Hidden, true),
+            (Match, false),
+            (Hidden, true),
+            (Context, true),
+            (Context, false),
+            (Match, false),
+        ];
+        assert_eq!(grep_sessions(&Grep::default(), &lines), "S1\n2\nS4\n6\n");
+
+        // a held line takes the banner along, and hands it on to the next
+        // line if it is dropped
+        let grep = Grep::default().with_context(1, 0);
+        assert_eq!(grep_sessions(&grep, &lines), "S1\n2\n--\nS4\n5\n6\n");
+        let lines = [(Context, true), (Context, false), (Match, false)];
+        assert_eq!(grep_sessions(&grep, &lines), "S1\n2\n3\n");
+    }
+}
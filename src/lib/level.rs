@@ -0,0 +1,218 @@
+use std::str::FromStr;
+
+/// The log levels we know how to display, in order of decreasing severity.
+///
+/// The discriminant is the index into the level label tables of the
+/// [`LineProcessor`](crate::LineProcessor).
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Unknown = 0,
+    Error = 1,
+    Warning = 2,
+    Info = 3,
+    Http = 4,
+    Verbose = 5,
+    Debug = 6,
+    Silly = 7,
+}
+
+impl Level {
+    /// Translates a level name to a level, ignoring case. Covers the level
+    /// vocabularies of the common loggers, i.e. 'warning' (logrus), 'WARN'
+    /// (slog), 'Information' (serilog) and 'dpanic' (zap).
+    pub fn from_name(name: &str) -> Level {
+        const NAMES: [(&str, Level); 20] = [
+            ("error", Level::Error),
+            ("warn", Level::Warning),
+            ("info", Level::Info),
+            ("http", Level::Http),
+            ("verbose", Level::Verbose),
+            ("debug", Level::Debug),
+            ("silly", Level::Silly),
+            ("err", Level::Error),
+            ("fatal", Level::Error),
+            ("panic", Level::Error),
+            ("dpanic", Level::Error),
+            ("critical", Level::Error),
+            ("crit", Level::Error),
+            ("alert", Level::Error),
+            ("emerg", Level::Error),
+            ("warning", Level::Warning),
+            ("information", Level::Info),
+            ("notice", Level::Info),
+            ("trace", Level::Silly),
+            ("dbg", Level::Debug),
+        ];
+        NAMES
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map_or(Level::Unknown, |(_, level)| *level)
+    }
+
+    /// Translates a numeric pino/bunyan level (10, 20, .., 60) to a level
+    pub fn from_pino(n: u64) -> Level {
+        match n {
+            0..=10 => Level::Silly,
+            11..=20 => Level::Debug,
+            21..=30 => Level::Info,
+            31..=40 => Level::Warning,
+            _ => Level::Error,
+        }
+    }
+
+    /// Translates a numeric syslog severity (0 - 7) to a level
+    pub fn from_syslog(n: u64) -> Level {
+        match n {
+            0..=3 => Level::Error,
+            4 => Level::Warning,
+            5 | 6 => Level::Info,
+            7 => Level::Debug,
+            _ => Level::Unknown,
+        }
+    }
+
+    /// The index of this level in the level label tables
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// A bit mask with just this level set, for use in [`LevelFilter`]
+    fn mask(self) -> u8 {
+        1 << self.index()
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    /// Parses a level name, as given on the command line. Besides the names
+    /// known by [`Level::from_name`], 'unknown' selects lines with levels we
+    /// do not recognize.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Level::from_name(s) {
+            Level::Unknown if s.eq_ignore_ascii_case("unknown") || s == "unk" => Ok(Level::Unknown),
+            Level::Unknown => Err(format!("unknown level '{s}'")),
+            level => Ok(level),
+        }
+    }
+}
+
+// --------------------------------------------------------------------------
+
+/// Decides which levels to show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelFilter {
+    min_level: Option<Level>,
+    allowed: u8,
+}
+
+impl LevelFilter {
+    /// A filter that shows all levels
+    pub fn all() -> Self {
+        Self {
+            min_level: None,
+            allowed: u8::MAX,
+        }
+    }
+
+    /// Show only levels at least as severe as `level`. Lines with unknown
+    /// levels can not be ranked, so they are still shown.
+    pub fn with_min_level(mut self, level: Level) -> Self {
+        self.min_level = Some(level);
+        self
+    }
+
+    /// Show only the given levels
+    pub fn with_only(mut self, levels: &[Level]) -> Self {
+        self.allowed = levels.iter().fold(0, |mask, level| mask | level.mask());
+        self
+    }
+
+    /// Never show the given levels
+    pub fn with_excluded(mut self, levels: &[Level]) -> Self {
+        self.allowed = levels
+            .iter()
+            .fold(self.allowed, |mask, level| mask & !level.mask());
+        self
+    }
+
+    /// Checks if lines with the given level should be shown
+    pub fn allows(&self, level: Level) -> bool {
+        let severe_enough = match self.min_level {
+            Some(min_level) => level == Level::Unknown || level <= min_level,
+            None => true,
+        };
+        severe_enough && self.allowed & level.mask() != 0
+    }
+}
+
+impl Default for LevelFilter {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+// --------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Level::from_name("info"), Level::Info);
+        assert_eq!(Level::from_name("WARN"), Level::Warning);
+        assert_eq!(Level::from_name("Information"), Level::Info);
+        assert_eq!(Level::from_name("fatal"), Level::Error);
+        assert_eq!(Level::from_name("30"), Level::Unknown);
+        assert_eq!(Level::from_name(""), Level::Unknown);
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("warn".parse::<Level>().unwrap(), Level::Warning);
+        assert_eq!("unknown".parse::<Level>().unwrap(), Level::Unknown);
+        assert!("loud".parse::<Level>().is_err());
+    }
+
+    #[test]
+    fn test_filter_min_level() {
+        let filter = LevelFilter::all().with_min_level(Level::Warning);
+        assert!(filter.allows(Level::Error));
+        assert!(filter.allows(Level::Warning));
+        assert!(!filter.allows(Level::Info));
+        assert!(!filter.allows(Level::Silly));
+        assert!(filter.allows(Level::Unknown));
+    }
+
+    #[test]
+    fn test_filter_only_and_excluded() {
+        let filter = LevelFilter::all().with_only(&[Level::Error, Level::Http]);
+        assert!(filter.allows(Level::Error));
+        assert!(filter.allows(Level::Http));
+        assert!(!filter.allows(Level::Info));
+        assert!(!filter.allows(Level::Unknown));
+
+        let filter = LevelFilter::all().with_excluded(&[Level::Silly, Level::Unknown]);
+        assert!(filter.allows(Level::Debug));
+        assert!(!filter.allows(Level::Silly));
+        assert!(!filter.allows(Level::Unknown));
+    }
+
+    #[test]
+    fn test_from_pino() {
+        assert_eq!(Level::from_pino(10), Level::Silly);
+        assert_eq!(Level::from_pino(30), Level::Info);
+        assert_eq!(Level::from_pino(50), Level::Error);
+        assert_eq!(Level::from_pino(60), Level::Error);
+    }
+
+    #[test]
+    fn test_from_syslog() {
+        assert_eq!(Level::from_syslog(3), Level::Error);
+        assert_eq!(Level::from_syslog(4), Level::Warning);
+        assert_eq!(Level::from_syslog(6), Level::Info);
+        assert_eq!(Level::from_syslog(8), Level::Unknown);
+    }
+}
@@ -1,37 +1,84 @@
 mod ansi;
+mod clock;
+mod decompress;
+mod extra_fields;
+mod fields;
+mod follow;
+mod framing;
+mod grep;
+mod level;
 mod line_processor;
 mod logline;
+mod merge;
+mod prefix;
+mod query;
+mod schema;
 mod session;
+mod stack;
+#[cfg(test)]
+mod test_util;
+mod time_range;
+mod timestamp;
 
-use std::path::PathBuf;
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
 
-pub use line_processor::LineProcessor;
+use sonic_rs::error::Category;
+
+pub use clock::TimeMode;
+pub use decompress::{Compression, DecompressReader};
+pub use extra_fields::{ExtraFields, NestedStyle};
+pub use fields::{FieldMap, FieldPath, FieldPathError};
+pub use follow::FollowReader;
+pub use framing::Framing;
+pub use grep::Grep;
+pub use level::{Level, LevelFilter};
+pub use line_processor::{
+    DEFAULT_MERGE_WINDOW, FORMAT_DETECTION_SAMPLE_SIZE, InvalidLines, LineProcessor, Skipped,
+};
+pub use prefix::LinePrefix;
+pub use query::{Query, QueryError};
+pub use schema::{Format, LevelEncoding, Schema};
 pub use session::SessionStartDetector;
+pub use time_range::{TimeBound, TimeRange};
+pub use timestamp::{TimeFormat, TimestampEncoding, time_zone, validate_format};
 
 // --------------------------------------------------------------------------
 
 /// Enumerates the kinds of errors that can happen during line processing
 #[derive(Debug)]
 pub enum ProcessError {
-    /// An error happened during JSON parsing of the individual log lines
-    ParseError(usize, sonic_rs::Error),
+    /// An error happened during JSON parsing of the individual log lines, in
+    /// the given file (if not std in), in the value starting at the given
+    /// line and column
+    ParseError(Option<PathBuf>, usize, usize, sonic_rs::Error),
     /// Could not open input file
     InputNotFound(PathBuf, std::io::Error),
-    /// An error happened while reading the input stream
-    ReadError(usize, std::io::Error),
+    /// Could not start reading std in (i.e. to find out if it is compressed)
+    StdinError(std::io::Error),
+    /// An error happened while reading the input stream, of the given file (if
+    /// not std in)
+    ReadError(Option<PathBuf>, usize, std::io::Error),
     /// An error happened while writing to the output stream
     WriteError(std::io::Error),
 }
 
 impl ProcessError {
     /// Creates a new process error from a parse error
-    fn from_parse_error(line_no: usize, value: sonic_rs::Error) -> ProcessError {
-        ProcessError::ParseError(line_no, value)
+    fn from_parse_error(
+        path: Option<&Path>,
+        pos: framing::Position,
+        value: sonic_rs::Error,
+    ) -> ProcessError {
+        let (line_no, column) = (pos.line_idx + 1, pos.column_idx + 1);
+        ProcessError::ParseError(path.map(Path::to_path_buf), line_no, column, value)
     }
 
     /// Creates a new process error from a read error
-    fn from_read_error(line_no: usize, value: std::io::Error) -> ProcessError {
-        ProcessError::ReadError(line_no, value)
+    fn from_read_error(path: Option<&Path>, line_no: usize, value: std::io::Error) -> ProcessError {
+        ProcessError::ReadError(path.map(Path::to_path_buf), line_no, value)
     }
 
     /// Creates a new process error from a write error
@@ -40,14 +87,58 @@ impl ProcessError {
     }
 }
 
+/// The position of a parse error in the input, and what went wrong. The
+/// position sonic-rs gives is within the value (counting columns from 0), which
+/// may start after other values on its line, or span several lines when the
+/// input is framed as JSON values.
+fn parse_error_position(
+    line_no: usize,
+    column: usize,
+    err: &sonic_rs::Error,
+) -> (usize, usize, Cow<'static, str>) {
+    match err.line() {
+        // errors of our own, without a position
+        0 => (line_no, column, Cow::Owned(err.to_string())),
+        1 => (line_no, column + err.column(), parse_error_kind(err)),
+        line => (line_no + line - 1, err.column() + 1, parse_error_kind(err)),
+    }
+}
+
+/// Describes a parse error by its kind
+fn parse_error_kind(err: &sonic_rs::Error) -> Cow<'static, str> {
+    Cow::Borrowed(match err.classify() {
+        Category::Eof => "unexpected end of the JSON value",
+        Category::Io => "could not read the JSON value",
+        _ => "invalid JSON",
+    })
+}
+
 impl std::fmt::Display for ProcessError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::ParseError(line_no, err) => write!(f, "Parse error on line {line_no}: {err}"),
+            Self::ParseError(None, line_no, column, err) => {
+                let (line_no, column, err) = parse_error_position(*line_no, *column, err);
+                write!(f, "Parse error on line {line_no}, column {column}: {err}")
+            }
+            Self::ParseError(Some(file_path), line_no, column, err) => {
+                let (line_no, column, err) = parse_error_position(*line_no, *column, err);
+                let file_path = file_path.display();
+                write!(
+                    f,
+                    "Parse error on line {line_no}, column {column} of '{file_path}': {err}"
+                )
+            }
             Self::InputNotFound(file_path, err) => {
                 write!(f, "Could not open input '{}': {err}", file_path.display())
             }
-            Self::ReadError(line_no, err) => write!(f, "Read error after line {line_no}: {err}"),
+            Self::StdinError(err) => write!(f, "Could not read std in: {err}"),
+            Self::ReadError(None, line_no, err) => {
+                write!(f, "Read error after line {line_no}: {err}")
+            }
+            Self::ReadError(Some(file_path), line_no, err) => {
+                let file_path = file_path.display();
+                write!(f, "Read error after line {line_no} of '{file_path}': {err}")
+            }
             Self::WriteError(err) => write!(f, "Write error: {err}"),
         }
     }
@@ -1,5 +1,38 @@
-use crate::{ProcessError, ansi_color, logline::LogLine, session::SessionStartDetector};
-use std::fmt::Write;
+use std::{
+    borrow::Cow,
+    fmt::Write,
+    fs::File,
+    io::{Cursor, Read, Seek, SeekFrom},
+    path::Path,
+    sync::{
+        OnceLock,
+        mpsc::{Receiver, sync_channel},
+    },
+    thread,
+};
+
+use jiff::{SignedDuration, Timestamp};
+
+use crate::{
+    ProcessError, ansi_color,
+    clock::{Clock, Tick, TimeMode, write_elapsed, write_gap},
+    extra_fields::{ExtraFields, FieldColors},
+    fields::FieldMap,
+    framing::{Framing, Position},
+    grep::{Grep, Selection},
+    level::{Level, LevelFilter},
+    logline::{LogLine, is_json_object},
+    merge::{MergeLine, MergeSender, merge, send_reordered},
+    prefix::{LinePrefix, Stripped},
+    query::Query,
+    schema::{Format, Schema, detect_format},
+    session::SessionStartDetector,
+    stack::{is_frame, is_library_frame},
+    time_range::{
+        Bounds, TimeRange, count_lines, head_lines, last_line_before, last_timestamp, seek_offset,
+    },
+    timestamp::TimeFormat,
+};
 
 // --------------------------------------------------------------------------
 
@@ -23,67 +56,644 @@ const DEFAULT_LEVEL_TABLE: [&str; 8] = [
     " [sil] ", // Silly
 ];
 
+// The level labels with their colors in front, and the colors on their own
+// (used for the continuation lines of messages), from the color of each level
+macro_rules! level_colors {
+    ($($n:literal $label:literal,)*) => {
+        (
+            [$(concat!(ansi_color!(fg: $n), $label)),*],
+            [$(ansi_color!(fg: $n)),*],
+        )
+    };
+}
+
 // See color table here https://en.wikipedia.org/wiki/ANSI_escape_code#8-bit
 //
 // Color names are from https://colornamer.robertcooper.me/
 //
-const DEFAULT_LEVEL_TABLE_COLOR: [&str; 8] = [
-    concat!(ansi_color!(fg: 5), " [unk] "),   // Unknown -> Purple
-    concat!(ansi_color!(fg: 9), " [err] "),   // Error -> Red
-    concat!(ansi_color!(fg: 11), " [wrn] "),  // Warning -> Yellow
-    concat!(ansi_color!(fg: 254), " [inf] "), // Info -> Titanium White
-    concat!(ansi_color!(fg: 97), " [web] "),  // Http -> Lusty Lavender
-    concat!(ansi_color!(fg: 6), " [vrb] "),   // Verbose -> Teal
-    concat!(ansi_color!(fg: 27), " [dbg] "),  // Debug -> Bright Blue
-    concat!(ansi_color!(fg: 27), " [sil] "),  // Silly -> Bright Blue
+const DEFAULT_LEVEL_COLOR_TABLES: ([&str; 8], [&str; 8]) = level_colors! {
+    5 " [unk] ",   // Unknown -> Purple
+    9 " [err] ",   // Error -> Red
+    11 " [wrn] ",  // Warning -> Yellow
+    254 " [inf] ", // Info -> Titanium White
+    97 " [web] ",  // Http -> Lusty Lavender
+    6 " [vrb] ",   // Verbose -> Teal
+    27 " [dbg] ",  // Debug -> Bright Blue
+    27 " [sil] ",  // Silly -> Bright Blue
+};
+
+const DEFAULT_LEVEL_TABLE_COLOR: [&str; 8] = DEFAULT_LEVEL_COLOR_TABLES.0;
+
+const DEFAULT_LEVEL_COLORS: [&str; 8] = DEFAULT_LEVEL_COLOR_TABLES.1;
+
+// The width of the level labels, without any color codes
+const LEVEL_LABEL_WIDTH: usize = 7;
+
+/// The number of lines held back per input when merging, to restore the order
+/// of lines that are slightly out of order
+pub const DEFAULT_MERGE_WINDOW: usize = 256;
+
+/// The number of JSON lines used to detect the log format of an input, when
+/// told to detect it
+pub const FORMAT_DETECTION_SAMPLE_SIZE: usize = 10;
+
+// The number of lines read ahead per input when merging
+const MERGE_CHANNEL_CAPACITY: usize = 1024;
+
+// The number of lines at the start of a file used to find its format and
+// first timestamp, before seeking to the start of the time range
+const SEEK_SAMPLE_SIZE: usize = 10;
+
+// The number of lines at the start of an input used to decide on the prefix
+// of its lines, when told to
+const PREFIX_DETECTION_SAMPLE_SIZE: usize = 10;
+
+// The colors of the source tags, picked by the name of the source
+const DEFAULT_SOURCE_COLORS: [&str; 6] = [
+    ansi_color!(fg: 71),  // Fern Green
+    ansi_color!(fg: 136), // Dark Goldenrod
+    ansi_color!(fg: 168), // Rosy Pink
+    ansi_color!(fg: 74),  // Sky Blue
+    ansi_color!(fg: 173), // Copper
+    ansi_color!(fg: 140), // Lilac
 ];
 
 // --------------------------------------------------------------------------
 
+/// The strings used to style the output
+struct Palette {
+    timestamp_prefix: &'static str,
+    highlight_prefix: &'static str,
+    level_table: [&'static str; 8],
+    level_colors: [&'static str; 8],
+    new_session_prefix: &'static str,
+    gap_prefix: &'static str,
+    frame_prefix: &'static str,
+    dim_prefix: &'static str,
+    raw_prefix: &'static str,
+    match_prefix: &'static str,
+    match_suffix: &'static str,
+    separator: &'static str,
+    source_colors: &'static [&'static str],
+    fields: FieldColors,
+    eol: &'static str,
+}
+
+const COLOR_PALETTE: Palette = Palette {
+    timestamp_prefix: ansi_color!(fg: 6),
+    highlight_prefix: ansi_color!(fg: 208), // Orange
+    level_table: DEFAULT_LEVEL_TABLE_COLOR,
+    level_colors: DEFAULT_LEVEL_COLORS,
+    new_session_prefix: ansi_color!(fg: 4),
+    gap_prefix: ansi_color!(fg: 240),   // Dark grey
+    frame_prefix: ansi_color!(fg: 244), // Grey
+    dim_prefix: ansi_color!(fg: 242),   // Darker grey
+    raw_prefix: ansi_color!(),          // The colors of the terminal
+    match_prefix: concat!(ansi_color!(bg: 94), ansi_color!(fg: 230)), // Cornsilk on Orange
+    match_suffix: ansi_color!(),
+    separator: concat!(ansi_color!(fg: 240), "--", ansi_color!(), "\n"),
+    source_colors: &DEFAULT_SOURCE_COLORS,
+    fields: FieldColors {
+        key: ansi_color!(fg: 67),    // Steel Blue
+        value: ansi_color!(fg: 250), // Silver
+        reset: ansi_color!(),
+    },
+    eol: END_OF_LINE_COLOR,
+};
+
+const NO_COLOR_PALETTE: Palette = Palette {
+    timestamp_prefix: "",
+    highlight_prefix: "",
+    level_table: DEFAULT_LEVEL_TABLE,
+    level_colors: [""; 8],
+    new_session_prefix: "",
+    gap_prefix: "",
+    frame_prefix: "",
+    dim_prefix: "",
+    raw_prefix: "",
+    match_prefix: "",
+    match_suffix: "",
+    separator: "--\n",
+    source_colors: &[""],
+    fields: FieldColors {
+        key: "",
+        value: "",
+        reset: "",
+    },
+    eol: END_OF_LINE,
+};
+
+// --------------------------------------------------------------------------
+
+/// What to do about lines that are not valid JSON objects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum InvalidLines {
+    /// Stop with an error
+    Abort,
+    /// Leave them out, noting the line number on std err
+    Skip,
+    /// Show them as they are
+    Raw,
+    /// Show them as they are, but dimmed
+    Dim,
+}
+
 /// Processes lines from the log (in jsonl format)
 pub struct LineProcessor<'a> {
     detector: SessionStartDetector,
-    skip_invalid_lines: bool,
-    timestamp_prefix: &'a str,
-    level_table: [&'a str; 8],
-    new_session_prefix: &'a str,
-    eol: &'a str,
+    schema: Schema,
+    field_overrides: FieldMap,
+    format_detection: Option<usize>,
+    follow: bool,
+    verbose: bool,
+    extra_fields: Option<ExtraFields>,
+    collapse_stack: bool,
+    with_filename: bool,
+    merge_window: usize,
+    time_format: TimeFormat,
+    time_mode: TimeMode,
+    highlight_delta: Option<SignedDuration>,
+    gap: Option<SignedDuration>,
+    time_range: TimeRange,
+    bounds: OnceLock<Bounds>,
+    grep: Grep,
+    query: Option<Query>,
+    level_filter: LevelFilter,
+    invalid_lines: InvalidLines,
+    prefix: LinePrefix,
+    framing: Framing,
+    palette: &'a Palette,
 }
 
 impl LineProcessor<'_> {
     /// Creates a new line processor
     ///
     pub fn new(detector: SessionStartDetector, skip_invalid_lines: bool, use_color: bool) -> Self {
-        let (new_session_prefix, eol, level_table, timestamp_prefix) = if use_color {
-            (
-                ansi_color!(fg: 4),
-                END_OF_LINE_COLOR,
-                DEFAULT_LEVEL_TABLE_COLOR,
-                ansi_color!(fg: 6),
-            )
+        let palette = if use_color {
+            &COLOR_PALETTE
         } else {
-            ("", END_OF_LINE, DEFAULT_LEVEL_TABLE, "")
+            &NO_COLOR_PALETTE
         };
 
         Self {
             detector,
-            skip_invalid_lines,
-            timestamp_prefix,
-            level_table,
-            new_session_prefix,
-            eol,
+            schema: Schema::default(),
+            field_overrides: FieldMap::empty(),
+            format_detection: None,
+            follow: false,
+            verbose: false,
+            extra_fields: None,
+            collapse_stack: false,
+            with_filename: false,
+            merge_window: DEFAULT_MERGE_WINDOW,
+            time_format: TimeFormat::default(),
+            time_mode: TimeMode::Absolute,
+            highlight_delta: None,
+            gap: None,
+            time_range: TimeRange::default(),
+            bounds: OnceLock::new(),
+            grep: Grep::default(),
+            query: None,
+            level_filter: LevelFilter::all(),
+            invalid_lines: match skip_invalid_lines {
+                true => InvalidLines::Skip,
+                false => InvalidLines::Abort,
+            },
+            prefix: LinePrefix::Auto,
+            framing: Framing::Lines,
+            palette,
+        }
+    }
+
+    /// Sets the JSON paths used to find the timestamp, level and message of
+    /// each line. Lists of paths left empty in `fields` keep the paths of
+    /// the schema (which defaults to winston)
+    ///
+    pub fn with_fields(mut self, fields: FieldMap) -> Self {
+        self.field_overrides = fields.overlay(&self.field_overrides);
+        self.schema = self.schema.with_fields(self.field_overrides.clone());
+        self
+    }
+
+    /// Sets the schema used to interpret each line (defaults to winston)
+    ///
+    pub fn with_schema(mut self, schema: Schema) -> Self {
+        self.schema = schema.with_fields(self.field_overrides.clone());
+        self
+    }
+
+    /// Sets the schema of the given log format. For journald, the entries are
+    /// unwrapped instead (unless a prefix other than journald's was set
+    /// before) and the format of their messages is detected, falling back to
+    /// the schema given up front. Messages without a level of their own, plain
+    /// text ones included, get the level of the priority of their entry.
+    ///
+    pub fn with_format(self, format: Format) -> Self {
+        match (format, self.prefix) {
+            (Format::Journald, LinePrefix::Auto | LinePrefix::Journald) => {
+                let sample_size = self
+                    .format_detection
+                    .unwrap_or(FORMAT_DETECTION_SAMPLE_SIZE);
+                self.with_prefix(LinePrefix::Journald)
+                    .with_format_detection(sample_size)
+            }
+            _ => self.with_schema(format.schema()),
         }
     }
 
+    /// Detect the format of the input from the first `sample_size` JSON
+    /// lines, instead of using the schema given up front. The given schema is
+    /// used if no format matches the sampled lines.
+    ///
+    pub fn with_format_detection(mut self, sample_size: usize) -> Self {
+        self.format_detection = Some(sample_size);
+        self
+    }
+
+    /// Render the given selection of the remaining fields of each line after
+    /// the message (by default they are not rendered)
+    ///
+    pub fn with_extra_fields(mut self, extra_fields: ExtraFields) -> Self {
+        self.extra_fields = Some(extra_fields);
+        self
+    }
+
+    /// Collapse runs of stack frames from library code (i.e. 'node_modules')
+    /// into a single line
+    ///
+    pub fn with_collapse_stack(mut self, collapse_stack: bool) -> Self {
+        self.collapse_stack = collapse_stack;
+        self
+    }
+
+    /// Prefix each line with a tag naming the file it came from, when
+    /// processing files with [`LineProcessor::process_file_lines`]
+    ///
+    pub fn with_filename(mut self, with_filename: bool) -> Self {
+        self.with_filename = with_filename;
+        self
+    }
+
+    /// Sets the number of lines held back per input when merging, which is
+    /// how far out of order lines can be and still be merged in order
+    ///
+    pub fn with_merge_window(mut self, merge_window: usize) -> Self {
+        self.merge_window = merge_window;
+        self
+    }
+
+    /// Sets how timestamps are shown (by default the time of day, in UTC)
+    ///
+    pub fn with_time_format(mut self, time_format: TimeFormat) -> Self {
+        self.time_format = time_format;
+        self
+    }
+
+    /// Sets what the time column shows, the timestamp of each line (the
+    /// default) or the time elapsed since some earlier line. The relative
+    /// times start over with each new session, except for
+    /// [`TimeMode::Relative`].
+    ///
+    pub fn with_time_mode(mut self, time_mode: TimeMode) -> Self {
+        self.time_mode = time_mode;
+        self
+    }
+
+    /// Highlight the time of lines that come more than `threshold` after the
+    /// line before them
+    ///
+    pub fn with_highlight_delta(mut self, threshold: SignedDuration) -> Self {
+        self.highlight_delta = Some(threshold);
+        self
+    }
+
+    /// Emit a marker line wherever more than `threshold` passes between one
+    /// line and the next
+    ///
+    pub fn with_gap(mut self, threshold: SignedDuration) -> Self {
+        self.gap = Some(threshold);
+        self
+    }
+
+    /// Only show lines within `time_range`. Times of day in the range are
+    /// taken on the date of the first line read, and lines without a (valid)
+    /// timestamp are left out.
+    ///
+    pub fn with_time_range(mut self, time_range: TimeRange) -> Self {
+        self.time_range = time_range;
+        self
+    }
+
+    /// Only show lines selected by `grep`, and the lines around them that it
+    /// asks for. Matches in the message are highlighted.
+    ///
+    pub fn with_grep(mut self, grep: Grep) -> Self {
+        self.grep = grep;
+        self
+    }
+
+    /// Only show lines with fields selected by `query`
+    ///
+    pub fn with_query(mut self, query: Query) -> Self {
+        self.query = Some(query);
+        self
+    }
+
+    /// Only show lines with the levels allowed by `level_filter`. Hidden
+    /// lines still count when detecting new sessions and numbering lines.
+    ///
+    pub fn with_level_filter(mut self, level_filter: LevelFilter) -> Self {
+        self.level_filter = level_filter;
+        self
+    }
+
+    /// Sets what to do about lines that are not valid JSON objects, in place
+    /// of the `skip_invalid_lines` flag given to [`LineProcessor::new`].
+    /// Invalid lines shown as they are are not filtered by level, query or
+    /// time range (even if the line before them is), but are left out along
+    /// with the line before them when it is not selected by the grep.
+    ///
+    pub fn with_invalid_lines(mut self, invalid_lines: InvalidLines) -> Self {
+        self.invalid_lines = invalid_lines;
+        self
+    }
+
+    /// Sets the kind of prefix to take off each line before parsing it as
+    /// JSON (by default the one most of the first lines of each input have).
+    /// What the prefix tells about the line (i.e. the container) is shown
+    /// in front of the time.
+    ///
+    pub fn with_prefix(mut self, prefix: LinePrefix) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    /// Sets how the input is split into records, one JSON object per line
+    /// (the default, and the fastest) or complete JSON values however they
+    /// are spread over lines. Records are numbered by the line they start on.
+    /// Either way, input starting with an array of objects (i.e. '[{') is read
+    /// as an array of records.
+    ///
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Tell if the input is followed as it grows. The format of a followed
+    /// input is not detected, as reading ahead for it would wait for lines to
+    /// be appended, and the schema given up front is used instead.
+    ///
+    pub fn with_follow(mut self, follow: bool) -> Self {
+        self.follow = follow;
+        self
+    }
+
+    /// Report what we are doing (i.e. which format was detected) on std err
+    ///
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
     /// Processes lines from the given [`BufRead`] implementation and writes
     /// each parsed line to the given [`Write`] implementation.
     ///
     /// Aborts and returns an error if we could not read from `lines` or write
-    /// to `w` at any point. If any of the lines are not parsable, we either
-    /// abort and return an error, or skip, depending on the setting of
-    /// `skip_invalid_lines`
+    /// to `w` at any point. If any of the lines are not parsable, we abort and
+    /// return an error, skip them or show them as they are, depending on the
+    /// [`InvalidLines`] policy
     ///
     pub fn process_lines<R, W>(&self, lines: R, w: &mut W) -> crate::Result<()>
+    where
+        R: std::io::BufRead,
+        W: std::io::Write,
+    {
+        self.process_input(Input::unnamed(), lines, w)
+    }
+
+    /// Processes lines read from the file at `path`, like
+    /// [`LineProcessor::process_lines`]. Errors name the file, and so does
+    /// the tag in front of each line if we have been asked to add one.
+    ///
+    pub fn process_file_lines<R, W>(&self, path: &Path, lines: R, w: &mut W) -> crate::Result<()>
+    where
+        R: std::io::BufRead,
+        W: std::io::Write,
+    {
+        self.process_file_lines_after(path, Skipped::default(), lines, w)
+    }
+
+    /// Processes the rest of the lines of the file at `path`, after the start
+    /// of it has been `skipped` (i.e. by [`LineProcessor::seek_to_time_range`]).
+    /// Line numbers in messages still count from the start of the file.
+    ///
+    pub fn process_file_lines_after<R, W>(
+        &self,
+        path: &Path,
+        skipped: Skipped,
+        lines: R,
+        w: &mut W,
+    ) -> crate::Result<()>
+    where
+        R: std::io::BufRead,
+        W: std::io::Write,
+    {
+        let input = if self.with_filename {
+            let color = source_color(self.palette, &file_name(path));
+            Input::tagged(path, color, 0)
+        } else {
+            Input::named(path)
+        };
+        match skipped.session_start {
+            // the line starting the session is read again, in place of the
+            // last line skipped, so the session starts with its banner
+            Some(session_start) => {
+                let input = Input {
+                    first_line_idx: skipped.lines.saturating_sub(1),
+                    ..input
+                };
+                let session_start = Cursor::new(session_start + "\n");
+                self.process_input(input, session_start.chain(lines), w)
+            }
+            None => {
+                let input = Input {
+                    first_line_idx: skipped.lines,
+                    ..input
+                };
+                self.process_input(input, lines, w)
+            }
+        }
+    }
+
+    /// Moves `file` to shortly before the first line in the time range, so the
+    /// lines before it do not have to be parsed, and returns what was skipped,
+    /// including the line starting the session we land in. Does nothing (and
+    /// skips nothing) unless the range has a start, the input has a record per
+    /// line and the file looks sorted by time, i.e. its last line is not older
+    /// than its first.
+    ///
+    pub fn seek_to_time_range(&self, path: &Path, file: &mut File) -> crate::Result<Skipped> {
+        let read_error = |err| ProcessError::from_read_error(Some(path), 0, err);
+        // the seek needs a record per line
+        if self.time_range.is_unbounded() || self.framing != Framing::Lines {
+            return Ok(Skipped::default());
+        }
+
+        let head = head_lines(file, SEEK_SAMPLE_SIZE).map_err(read_error)?;
+        let prefix = self.prefix.detect(head.iter().map(String::as_str));
+        let schema = match self.format_detection {
+            Some(_) => detect_format(&strip_all(prefix, &head))
+                .map(|format| format.schema().with_fields(self.field_overrides.clone()))
+                .unwrap_or_else(|| self.schema.clone()),
+            None => self.schema.clone(),
+        };
+        let timestamp_of = |line: &str| {
+            let stripped = prefix.strip(line);
+            let log_line = stripped.parse(schema.fields()).ok()?;
+            schema.timestamps().parse(log_line.timestamp())
+        };
+        let starts_session = |line: &str| {
+            let stripped = prefix.strip(line);
+            stripped
+                .parse(schema.fields())
+                .is_ok_and(|log_line| self.detector.is_new_session(&log_line))
+        };
+
+        let first = head.iter().find_map(|line| timestamp_of(line));
+        let last = last_timestamp(file, timestamp_of).map_err(read_error)?;
+        let offset = match (first, last) {
+            (Some(first), Some(last)) if first <= last => match self.bounds(first).since {
+                Some(since) => seek_offset(file, since, timestamp_of).map_err(read_error)?,
+                None => 0,
+            },
+            // not JSON lines (i.e. compressed), or not sorted
+            _ => 0,
+        };
+        // the lines skipped are counted, so line numbers in messages stay
+        // right
+        let lines = count_lines(file, offset).map_err(read_error)?;
+        let session_start = match (offset, &self.detector) {
+            (0, _) | (_, SessionStartDetector::Never) => None,
+            _ => last_line_before(file, offset, starts_session)
+                .map_err(read_error)?
+                .map(|(_, line)| line),
+        };
+        file.seek(SeekFrom::Start(offset)).map_err(read_error)?;
+        Ok(Skipped {
+            bytes: offset,
+            lines,
+            session_start,
+        })
+    }
+
+    /// Processes the lines of several files, read concurrently, and writes them
+    /// in timestamp order. Each line is tagged with the name of its file.
+    ///
+    /// Lines that are out of order within a file are put in order, as long as
+    /// they are no more than the merge window apart. Lines without a (valid)
+    /// timestamp stay with the line before them.
+    ///
+    pub fn process_merged<R, W>(&self, files: Vec<(&Path, R)>, w: &mut W) -> crate::Result<()>
+    where
+        R: std::io::BufRead + Send,
+        W: std::io::Write,
+    {
+        let name_width = files
+            .iter()
+            .map(|(path, _)| file_name(path).chars().count())
+            .max()
+            .unwrap_or_default();
+
+        // detect the schemas up front, so the rendering knows them
+        let mut inputs = Vec::with_capacity(files.len());
+        let mut readers = Vec::with_capacity(files.len());
+        for (idx, (path, lines)) in files.into_iter().enumerate() {
+            let input = Input::named(path);
+            let mut lines = self.framing.records(lines);
+            let mut sample = Vec::new();
+            let prefix = self.detect_prefix(&input, &mut lines, &mut sample)?;
+            let input = input.with_prefix(prefix);
+            let schema = match self.format_detection {
+                Some(sample_size) => {
+                    self.detect_schema(&input, &mut lines, sample_size, &mut sample)?
+                }
+                None => self.schema.clone(),
+            };
+            let color = self.palette.source_colors[idx % self.palette.source_colors.len()];
+            let input = Input::tagged(path, color, name_width).with_prefix(prefix);
+            inputs.push((input, schema));
+            let sampled = sample.into_iter().map(|(idx, line)| (idx, Ok(line)));
+            readers.push(prefix.join_partial_lines(sampled.chain(lines)));
+        }
+
+        // one thread per input reads (and orders) its lines, while we write
+        // them out here
+        thread::scope(|scope| {
+            let mut receivers = Vec::with_capacity(inputs.len());
+            for ((input, schema), lines) in inputs.iter().zip(readers) {
+                let (tx, rx) = sync_channel(MERGE_CHANNEL_CAPACITY);
+                scope.spawn(move || self.read_for_merge(input, schema, lines, &tx));
+                receivers.push(rx);
+            }
+            self.write_merged(&inputs, receivers, w)
+        })
+    }
+
+    /// Reads the lines of an input to be merged and sends them on, in order,
+    /// along with their timestamps
+    fn read_for_merge<I>(&self, input: &Input, schema: &Schema, lines: I, tx: &MergeSender)
+    where
+        I: Iterator<Item = (Position, std::io::Result<String>)>,
+    {
+        let mut last_key = i128::MIN;
+        let lines = lines.map(|(pos, line)| {
+            let line_no = pos.line_idx + 1;
+            let line = line.map_err(|e| ProcessError::from_read_error(input.path, line_no, e))?;
+            let key = input
+                .prefix
+                .strip(&line)
+                .parse(schema.fields())
+                .ok()
+                .and_then(|log_line| schema.timestamps().to_nanos(log_line.timestamp()))
+                .unwrap_or(last_key);
+            last_key = key;
+            Ok(MergeLine { key, pos, line })
+        });
+        send_reordered(lines, self.merge_window, tx);
+    }
+
+    /// Writes the merged lines of the inputs
+    fn write_merged<W>(
+        &self,
+        inputs: &[(Input, Schema)],
+        receivers: Vec<Receiver<crate::Result<MergeLine>>>,
+        w: &mut W,
+    ) -> crate::Result<()>
+    where
+        W: std::io::Write,
+    {
+        let mut rendered = Rendered::with_capacity(8192);
+        let mut clock = Clock::default();
+        let mut context = self.grep.context(self.palette.separator);
+        merge(receivers, |idx, merge_line| {
+            let (input, schema) = &inputs[idx];
+            rendered.clear();
+            let selection = self.process_line(
+                schema,
+                input,
+                &mut clock,
+                merge_line.pos,
+                merge_line.line,
+                &mut rendered,
+            )?;
+            context
+                .write(selection, &rendered.banner, &rendered.line, w)
+                .map_err(ProcessError::from_write_error)
+        })
+    }
+
+    /// Processes lines from the given input
+    fn process_input<R, W>(&self, input: Input, lines: R, w: &mut W) -> crate::Result<()>
     where
         R: std::io::BufRead,
         W: std::io::Write,
@@ -91,138 +701,1504 @@ impl LineProcessor<'_> {
         // The line buffer is re-used for all lines, so giving it a large-ish
         // capacity will minimize runtime allocations, assuming the vast
         // majority of log lines will be less than 8Kib bytes long.
-        let mut line_buffer = String::with_capacity(8192);
+        let mut rendered = Rendered::with_capacity(8192);
+
+        // when detecting the prefix or format we have to read ahead, and the
+        // sampled lines are then processed before the rest of the input
+        let first_line_idx = input.first_line_idx;
+        let mut lines = self
+            .framing
+            .records(lines)
+            .map(|(pos, line)| (pos.after_lines(first_line_idx), line));
+        let mut sample = Vec::new();
+        let prefix = self.detect_prefix(&input, &mut lines, &mut sample)?;
+        let input = &input.with_prefix(prefix);
+        let detected_schema;
+        let schema = match self.format_detection {
+            Some(_) if self.follow => {
+                if self.verbose {
+                    eprintln!("--- not detecting the log format of a followed input ---");
+                }
+                &self.schema
+            }
+            Some(sample_size) => {
+                detected_schema =
+                    self.detect_schema(input, &mut lines, sample_size, &mut sample)?;
+                &detected_schema
+            }
+            None => &self.schema,
+        };
+
+        let mut clock = Clock::default();
+        let mut context = self.grep.context(self.palette.separator);
 
         // we track the line number of the log for debugging (invalid json lines
         // in the log, for example)
-        let mut line_no = 1;
-        for line in lines.lines() {
-            line_buffer.clear();
-            let line = line.map_err(|e| ProcessError::from_read_error(line_no, e))?;
-            self.process_line(line_no, line, &mut line_buffer)?;
-            w.write_all(line_buffer.as_bytes())
+        let sampled = sample.into_iter().map(|(idx, line)| (idx, Ok(line)));
+        for (pos, line) in prefix.join_partial_lines(sampled.chain(lines)) {
+            rendered.clear();
+            let line =
+                line.map_err(|e| ProcessError::from_read_error(input.path, pos.line_idx + 1, e))?;
+            let selection =
+                self.process_line(schema, input, &mut clock, pos, line, &mut rendered)?;
+            context
+                .write(selection, &rendered.banner, &rendered.line, w)
                 .map_err(ProcessError::from_write_error)?;
-            line_no += 1;
         }
         Ok(())
     }
 
+    /// Reads lines into `sample` until it holds enough of them (or the input
+    /// ends) to decide on the prefix of the lines, if we have been told to.
+    /// When following, only the first line is read, as more may take a while.
+    ///
+    fn detect_prefix<I>(
+        &self,
+        input: &Input,
+        lines: &mut I,
+        sample: &mut Vec<(Position, String)>,
+    ) -> crate::Result<LinePrefix>
+    where
+        I: Iterator<Item = (Position, std::io::Result<String>)>,
+    {
+        if self.prefix != LinePrefix::Auto {
+            return Ok(self.prefix);
+        }
+        let sample_size = match self.follow {
+            true => 1,
+            false => PREFIX_DETECTION_SAMPLE_SIZE,
+        };
+        while sample.len() < sample_size {
+            let Some((pos, line)) = lines.next() else {
+                break;
+            };
+            let line =
+                line.map_err(|e| ProcessError::from_read_error(input.path, pos.line_idx + 1, e))?;
+            sample.push((pos, line));
+        }
+        Ok(self
+            .prefix
+            .detect(sample.iter().map(|(_, line)| line.as_str())))
+    }
+
+    /// Reads lines into `sample` until it holds `sample_size` JSON lines (or
+    /// the input ends), and picks the schema matching them best
+    ///
+    fn detect_schema<I>(
+        &self,
+        input: &Input,
+        lines: &mut I,
+        sample_size: usize,
+        sample: &mut Vec<(Position, String)>,
+    ) -> crate::Result<Schema>
+    where
+        I: Iterator<Item = (Position, std::io::Result<String>)>,
+    {
+        let is_json = |line: &str| is_json_object(&input.prefix.strip(line).json);
+        let mut json_lines = sample.iter().filter(|(_, line)| is_json(line)).count();
+        while json_lines < sample_size {
+            let Some((pos, line)) = lines.next() else {
+                break;
+            };
+            let line =
+                line.map_err(|e| ProcessError::from_read_error(input.path, pos.line_idx + 1, e))?;
+            if is_json(&line) {
+                json_lines += 1;
+            }
+            sample.push((pos, line));
+        }
+
+        match detect_format(&strip_all(
+            input.prefix,
+            sample.iter().map(|(_, line)| line),
+        )) {
+            Some(format) => {
+                if self.verbose {
+                    eprintln!("--- detected log format '{format}' ---");
+                }
+                Ok(format.schema().with_fields(self.field_overrides.clone()))
+            }
+            None => {
+                if self.verbose {
+                    eprintln!("--- could not detect log format ---");
+                }
+                Ok(self.schema.clone())
+            }
+        }
+    }
+
+    /// The time range resolved to points in time, given the timestamp of the
+    /// first line (if it has not been resolved already)
+    fn bounds(&self, first: Timestamp) -> &Bounds {
+        self.bounds
+            .get_or_init(|| self.time_range.resolve(first, &self.time_format.zone()))
+    }
+
     /// Helper function that processes a single line of the log
     ///
     fn process_line(
         &self,
-        line_no: usize,
+        schema: &Schema,
+        input: &Input,
+        clock: &mut Clock,
+        pos: Position,
         line: String,
-        line_buffer: &mut String,
-    ) -> crate::Result<()> {
-        // parse the JSON line
-        match sonic_rs::from_slice::<LogLine>(line.as_bytes()) {
-            Err(err) => {
-                if !self.skip_invalid_lines {
-                    Err(ProcessError::from_parse_error(line_no, err))
-                } else {
-                    eprintln!("--- skipped non-json line @ {line_no} ---");
-                    Ok(())
+        dest: &mut Rendered,
+    ) -> crate::Result<Selection> {
+        // parse the JSON line, after taking off any prefix
+        let stripped = input.prefix.strip(&line);
+        match stripped.parse(schema.fields()) {
+            Err(err) => match self.invalid_lines {
+                InvalidLines::Abort => {
+                    // the error is at a column of the JSON, after the prefix
+                    let pos = pos.after_columns(stripped.json_offset(&line));
+                    Err(ProcessError::from_parse_error(input.path, pos, err))
                 }
-            }
+                InvalidLines::Skip => {
+                    let line_no = pos.line_idx + 1;
+                    if let Some(path) = input.path {
+                        let path = path.display();
+                        eprintln!("--- skipped non-json line @ {path}:{line_no} ---");
+                    } else {
+                        eprintln!("--- skipped non-json line @ {line_no} ---");
+                    }
+                    Ok(Selection::Hidden)
+                }
+                InvalidLines::Raw | InvalidLines::Dim => {
+                    self.write_invalid_line(input, &mut dest.line, &line);
+                    Ok(Selection::Continuation)
+                }
+            },
             Ok(log_line) => {
-                self.write_log_line(line_buffer, &log_line);
-                Ok(())
+                Ok(self.write_log_line(schema, input, &stripped, clock, dest, &log_line))
+            }
+        }
+    }
+
+    /// Write a line that is not valid JSON as it is, possibly dimmed
+    fn write_invalid_line(&self, input: &Input, dest: &mut String, line: &str) {
+        let palette = self.palette;
+        dest.push_str(&input.tag);
+        match self.invalid_lines {
+            InvalidLines::Dim => {
+                dest.push_str(palette.dim_prefix);
+                dest.push_str(line.trim_end_matches('\r'));
+                dest.push_str(palette.eol);
+            }
+            _ => {
+                dest.push_str(palette.raw_prefix);
+                dest.push_str(line.trim_end_matches('\r'));
+                dest.push_str(palette.eol);
             }
         }
     }
 
-    /// Write the parsed log line to the destination
-    fn write_log_line(&self, dest: &mut String, log_line: &LogLine) {
+    /// Write the parsed log line to the destination, and tell if it is to be
+    /// shown
+    fn write_log_line(
+        &self,
+        schema: &Schema,
+        input: &Input,
+        stripped: &Stripped,
+        clock: &mut Clock,
+        rendered: &mut Rendered,
+        log_line: &LogLine,
+    ) -> Selection {
+        let Rendered { banner, line: dest } = rendered;
+        let palette = self.palette;
+        let timestamps = schema.timestamps();
+        let time_format = &self.time_format;
+
         // if we discover a new session has started, we emit that as a line
-        // before the first event line
+        // before the first line of it that is shown. Every line is looked at,
+        // so a session starts even if the line starting it is filtered out.
         if self.detector.is_new_session(log_line) {
-            dest.push_str(self.new_session_prefix);
-            writeln!(
-                dest,
-                "---- Session started {} ----",
-                &log_line.timestamp()[..10]
-            )
-            .unwrap();
+            banner.push_str(palette.new_session_prefix);
+            banner.push_str("---- Session started ");
+            time_format.write_date(banner, timestamps, log_line.timestamp());
+            banner.push_str(" ----\n");
+            clock.start_session();
         }
 
-        // timestamp using only the time part, i.e. '2025-01-01T23:34:56.987Z'
-        // emits as '23:34:56.987Z'
-        dest.push_str(self.timestamp_prefix);
-        dest.push_str(&log_line.timestamp()[11..]);
+        if !self.time_range.is_unbounded() {
+            let ts = timestamps.parse(log_line.timestamp());
+            if !ts.is_some_and(|ts| self.bounds(ts).contains(ts)) {
+                return Selection::Hidden;
+            }
+        }
 
-        // level, translated to a fixed-sized version
-        dest.push_str(self.get_level_label(log_line.level()));
+        // lines without a level of their own get the level of the prefix
+        let level = match (log_line.level(), stripped.level) {
+            ("", Some(level)) => level,
+            (level, _) => schema.level(level),
+        };
+        if !self.level_filter.allows(level) {
+            return Selection::Hidden;
+        }
+        if let Some(query) = &self.query
+            && !query.matches(log_line.source())
+        {
+            return Selection::Hidden;
+        }
+        let selection = match self.grep.matches(log_line) {
+            true => Selection::Match,
+            false => Selection::Context,
+        };
 
-        // message (reusing the color state from level)
-        dest.push_str(log_line.message());
+        // the time elapsed since an earlier line, if we are showing that or
+        // looking for long gaps between lines
+        let tick = if self.time_mode != TimeMode::Absolute
+            || self.highlight_delta.is_some()
+            || self.gap.is_some()
+        {
+            clock.tick(self.time_mode, timestamps.parse(log_line.timestamp()))
+        } else {
+            Tick::default()
+        };
 
-        // reset colors and write new line
-        dest.push_str(self.eol);
-    }
-
-    /// Get the fixed-size label for a given level string, possibly prefixed
-    /// with color codes.
-    fn get_level_label(&self, level: &str) -> &str {
-        match level {
-            "error" => self.level_table[1],
-            "warn" => self.level_table[2],
-            "info" => self.level_table[3],
-            "http" => self.level_table[4],
-            "verbose" => self.level_table[5],
-            "debug" => self.level_table[6],
-            "silly" => self.level_table[7],
-            _ => self.level_table[0], // Unknown
+        // a long pause before this line gets a line of its own
+        if tick.exceeds(self.gap) {
+            dest.push_str(palette.gap_prefix);
+            dest.push_str("---- ");
+            write_gap(dest, tick.delta.unwrap_or_default());
+            dest.push_str(" gap ----\n");
         }
-    }
-}
 
-// --------------------------------------------------------------------------
+        // the tag naming the source file, if any
+        dest.push_str(&input.tag);
 
-#[cfg(test)]
-mod tests {
-    use std::io::{BufRead, Cursor};
+        // what the prefix of the line told about it, if anything
+        let context_width = match stripped.context.as_deref() {
+            Some(context) => {
+                write!(dest, "{}[{context}] ", palette.dim_prefix).unwrap();
+                context.chars().count() + 3
+            }
+            None => 0,
+        };
 
-    use super::*;
+        // timestamp using only the time part, i.e. '2025-01-01T23:34:56.987Z'
+        // emits as '23:34:56.987Z', or the elapsed time, i.e. '+0.153s'
+        dest.push_str(if tick.exceeds(self.highlight_delta) {
+            palette.highlight_prefix
+        } else {
+            palette.timestamp_prefix
+        });
+        let timestamp_start = dest.len();
+        match tick.elapsed {
+            Some(elapsed) => write_elapsed(dest, elapsed),
+            None => time_format.write_time(dest, timestamps, log_line.timestamp()),
+        }
+        let timestamp_width = dest[timestamp_start..].chars().count();
 
-    #[test]
-    fn test_process_lines_with_valid_lines() {
-        let detector = SessionStartDetector::Never;
-        let processor = LineProcessor::new(detector, false, false);
+        // level, translated to a fixed-sized version
+        dest.push_str(self.get_level_label(level));
 
-        let input =
-            r#"{"timestamp":"2023-01-01T12:00:00Z","level":"info","message":"Test message"}"#;
+        // message (reusing the color state from level), only the first line
+        // for now
+        let level_color = palette.level_colors[level.index()];
+        let mut message_lines = log_line.message().trim_end_matches(['\r', '\n']).lines();
+        self.write_highlighted(dest, message_lines.next().unwrap_or_default(), level_color);
 
-        let mut output = Vec::new();
-        processor
-            .process_lines(Cursor::new(input), &mut output)
-            .unwrap();
-        let output = String::from_utf8(output).unwrap();
-        assert_eq!(output, "12:00:00Z [inf] Test message\n");
-    }
+        // the remaining fields, where nested values might go on the lines
+        // after this one
+        let mut nested = String::new();
+        if let Some(extra_fields) = &self.extra_fields {
+            extra_fields.write(
+                dest,
+                &mut nested,
+                log_line.source(),
+                schema.fields(),
+                &palette.fields,
+            );
+        }
 
-    const INVALID_LINES_SOURCE: &str = include_str!("../../test-data/log-with-invalid-lines.jsonl");
+        // reset colors and write new line
+        dest.push_str(palette.eol);
 
-    #[test]
-    fn test_process_lines_with_invalid_lines_skip() {
-        let detector = SessionStartDetector::Never;
-        let processor = LineProcessor::new(detector, true, false);
+        // the rest of the message and the stack trace (if any) go on their own
+        // lines, lined up with the start of the message
+        let indent = input.tag_width + context_width + timestamp_width + LEVEL_LABEL_WIDTH;
+        let stack_lines = log_line.stack().trim_end_matches(['\r', '\n']).lines();
+        self.write_continuation_lines(dest, message_lines.chain(stack_lines), indent, level_color);
 
-        let mut output = Vec::new();
+        dest.push_str(&nested);
+        selection
+    }
 
-        processor
-            .process_lines(Cursor::new(INVALID_LINES_SOURCE), &mut output)
-            .unwrap();
+    /// Write part of a message, highlighting the matches of the grep (if any).
+    /// After each match, `color` is restored.
+    fn write_highlighted(&self, dest: &mut String, text: &str, color: &str) {
+        let palette = self.palette;
+        let rx = match self.grep.message() {
+            Some(rx) if !palette.match_prefix.is_empty() => rx,
+            _ => {
+                dest.push_str(text);
+                return;
+            }
+        };
 
-        let output_lines = Cursor::new(output)
-            .lines()
-            .collect::<Result<Vec<_>, _>>()
-            .unwrap();
+        let mut last = 0;
+        for m in rx.find_iter(text).filter(|m| !m.is_empty()) {
+            dest.push_str(&text[last..m.start()]);
+            dest.push_str(palette.match_prefix);
+            dest.push_str(m.as_str());
+            dest.push_str(palette.match_suffix);
+            dest.push_str(color);
+            last = m.end();
+        }
+        dest.push_str(&text[last..]);
+    }
 
-        assert_eq!(output_lines.len(), 3);
-        assert_eq!(output_lines[0], "20:25:01.133Z [inf] First valid line");
-        assert_eq!(output_lines[1], "20:26:01.133Z [wrn] Second valid line");
-        assert_eq!(output_lines[2], "20:27:01.133Z [inf] Third valid line");
+    /// Write the continuation lines of a message, indented by `indent`. Stack
+    /// frames are dimmed, and runs of library frames are collapsed if we have
+    /// been asked to.
+    fn write_continuation_lines<'l>(
+        &self,
+        dest: &mut String,
+        lines: impl Iterator<Item = &'l str>,
+        indent: usize,
+        level_color: &str,
+    ) {
+        let palette = self.palette;
+        let mut collapsed_frames = 0;
+        for line in lines {
+            let line = line.trim_end_matches('\r');
+            let is_frame = is_frame(line);
+            if is_frame && self.collapse_stack && is_library_frame(line) {
+                collapsed_frames += 1;
+                continue;
+            }
+            self.write_collapsed_frames(dest, indent, collapsed_frames);
+            collapsed_frames = 0;
+
+            if is_frame {
+                write!(dest, "{:indent$}{}{line}", "", palette.frame_prefix).unwrap();
+            } else {
+                write!(dest, "{:indent$}{level_color}", "").unwrap();
+                self.write_highlighted(dest, line, level_color);
+            }
+            dest.push_str(palette.eol);
+        }
+        self.write_collapsed_frames(dest, indent, collapsed_frames);
+    }
+
+    /// Write a line in place of a number of collapsed library frames
+    fn write_collapsed_frames(&self, dest: &mut String, indent: usize, count: usize) {
+        if count > 0 {
+            let plural = if count == 1 { "" } else { "s" };
+            write!(
+                dest,
+                "{:indent$}{}    ... {count} library frame{plural}{}",
+                "", self.palette.frame_prefix, self.palette.eol
+            )
+            .unwrap();
+        }
+    }
+
+    /// Get the fixed-size label for a given level, possibly prefixed with
+    /// color codes.
+    fn get_level_label(&self, level: Level) -> &str {
+        self.palette.level_table[level.index()]
+    }
+}
+
+// --------------------------------------------------------------------------
+
+/// A line rendered for output, and the banner of the session it starts (if
+/// it does)
+struct Rendered {
+    banner: String,
+    line: String,
+}
+
+impl Rendered {
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            banner: String::new(),
+            line: String::with_capacity(capacity),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.banner.clear();
+        self.line.clear();
+    }
+}
+
+/// How much of the start of a file was skipped, without processing it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Skipped {
+    /// The number of bytes skipped
+    pub bytes: u64,
+    /// The number of lines skipped
+    pub lines: usize,
+    /// The last line skipped that starts a session, if any
+    pub session_start: Option<String>,
+}
+
+/// Where the lines being processed come from
+struct Input<'p> {
+    /// The file the lines are read from, `None` for std in
+    path: Option<&'p Path>,
+    /// The index of the first line read, if the lines before it were skipped
+    first_line_idx: usize,
+    /// The prefix of the lines, once decided on
+    prefix: LinePrefix,
+    /// The tag written in front of each line, possibly empty
+    tag: String,
+    /// The width of the tag, without any color codes
+    tag_width: usize,
+}
+
+impl<'p> Input<'p> {
+    /// Lines from std in (or some other unnamed stream)
+    fn unnamed() -> Self {
+        Self {
+            path: None,
+            first_line_idx: 0,
+            prefix: LinePrefix::Auto,
+            tag: String::new(),
+            tag_width: 0,
+        }
+    }
+
+    /// Lines from a file, without tags
+    fn named(path: &'p Path) -> Self {
+        Self {
+            path: Some(path),
+            ..Self::unnamed()
+        }
+    }
+
+    /// Lines from a file, tagged with the file name in the given color, and
+    /// padded to (at least) `name_width` characters
+    fn tagged(path: &'p Path, color: &str, name_width: usize) -> Self {
+        let name = file_name(path);
+        // the name, a colon and padding
+        let width = name_width.max(name.chars().count()) + 1;
+        Self {
+            path: Some(path),
+            first_line_idx: 0,
+            prefix: LinePrefix::Auto,
+            tag: format!("{color}{:width$} ", format!("{name}:")),
+            tag_width: width + 1,
+        }
+    }
+
+    /// The same input, with the prefix of its lines decided on
+    fn with_prefix(self, prefix: LinePrefix) -> Self {
+        Self { prefix, ..self }
+    }
+}
+
+/// Takes the prefixes off the sampled lines, for detecting their format
+fn strip_all<'l>(
+    prefix: LinePrefix,
+    lines: impl IntoIterator<Item = &'l String>,
+) -> Vec<Cow<'l, str>> {
+    lines
+        .into_iter()
+        .map(|line| prefix.strip(line).json)
+        .collect()
+}
+
+/// The name of a file, without the directories
+fn file_name(path: &Path) -> Cow<'_, str> {
+    path.file_name()
+        .map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy())
+}
+
+/// Picks a color for a source by its name, so each file keeps its color
+/// between runs
+fn source_color(palette: &Palette, name: &str) -> &'static str {
+    let hash = name.bytes().fold(0usize, |hash, b| {
+        hash.wrapping_mul(31).wrapping_add(b as usize)
+    });
+    palette.source_colors[hash % palette.source_colors.len()]
+}
+
+// --------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, Cursor};
+
+    use super::*;
+    use crate::test_util::xorshift;
+
+    #[test]
+    fn test_process_lines_with_valid_lines() {
+        let detector = SessionStartDetector::Never;
+        let processor = LineProcessor::new(detector, false, false);
+
+        let input =
+            r#"{"timestamp":"2023-01-01T12:00:00Z","level":"info","message":"Test message"}"#;
+
+        let mut output = Vec::new();
+        processor
+            .process_lines(Cursor::new(input), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, "12:00:00.000Z [inf] Test message\n");
+    }
+
+    #[test]
+    fn test_process_lines_with_custom_fields() {
+        let detector = SessionStartDetector::Never;
+        let fields = FieldMap::default()
+            .with_timestamp(crate::fields::paths(&["time"]))
+            .with_level(crate::fields::paths(&["log.level"]))
+            .with_message(crate::fields::paths(&["msg"]));
+        let processor = LineProcessor::new(detector, false, false).with_fields(fields);
+
+        let input =
+            r#"{"time":"2023-01-01T12:00:00Z","log":{"level":"warn"},"msg":"Test message"}"#;
+
+        let mut output = Vec::new();
+        processor
+            .process_lines(Cursor::new(input), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, "12:00:00.000Z [wrn] Test message\n");
+    }
+
+    #[test]
+    fn test_process_lines_with_pino_schema() {
+        let detector = SessionStartDetector::Never;
+        let processor =
+            LineProcessor::new(detector, false, false).with_schema(crate::Format::Pino.schema());
+
+        let input = r#"{"level":40,"time":1744316701133,"pid":1,"msg":"Test message"}"#;
+
+        let mut output = Vec::new();
+        processor
+            .process_lines(Cursor::new(input), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, "20:25:01.133Z [wrn] Test message\n");
+    }
+
+    #[test]
+    fn test_process_lines_with_format_detection() {
+        let detector = SessionStartDetector::Never;
+        let processor = LineProcessor::new(detector, false, false).with_format_detection(1);
+
+        let input = concat!(
+            r#"{"level":40,"time":1744316701133,"msg":"First message"}"#,
+            "\n",
+            r#"{"level":30,"time":1744316702133,"msg":"Second message"}"#,
+        );
+
+        let mut output = Vec::new();
+        processor
+            .process_lines(Cursor::new(input), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "20:25:01.133Z [wrn] First message\n20:25:02.133Z [inf] Second message\n"
+        );
+    }
+
+    #[test]
+    fn test_process_lines_with_extra_fields() {
+        let detector = SessionStartDetector::Never;
+        let processor =
+            LineProcessor::new(detector, false, false).with_extra_fields(ExtraFields::all());
+
+        let input = r#"{"timestamp":"2023-01-01T12:00:00Z","level":"info","message":"Test message","requestId":"abc","durationMs":12}"#;
+
+        let mut output = Vec::new();
+        processor
+            .process_lines(Cursor::new(input), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "12:00:00.000Z [inf] Test message requestId=abc durationMs=12\n"
+        );
+    }
+
+    #[test]
+    fn test_process_lines_with_delta_time() {
+        let detector = SessionStartDetector::from_message("Starting");
+        let processor = LineProcessor::new(detector, false, true)
+            .with_time_mode(TimeMode::Delta)
+            .with_highlight_delta(SignedDuration::from_secs(1));
+
+        let input = concat!(
+            r#"{"timestamp":"2023-01-01T12:00:00Z","level":"info","message":"First"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:00:00.153Z","level":"info","message":"Second"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:00:05Z","level":"info","message":"Slow"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:01:00Z","level":"info","message":"Starting"}"#,
+        );
+
+        let mut output = Vec::new();
+        processor
+            .process_lines(Cursor::new(input), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert!(lines[0].starts_with(concat!(ansi_color!(fg: 6), "   +0.000s")));
+        assert!(lines[1].starts_with(concat!(ansi_color!(fg: 6), "   +0.153s")));
+        assert!(lines[2].starts_with(concat!(ansi_color!(fg: 208), "   +4.847s")));
+        // the delta shown starts over with the new session, but the pause
+        // before it is still highlighted
+        assert!(lines[3].contains("Session started"));
+        assert!(lines[4].starts_with(concat!(ansi_color!(fg: 208), "   +0.000s")));
+    }
+
+    #[test]
+    fn test_process_lines_with_gap_markers() {
+        let detector = SessionStartDetector::from_message("Starting");
+        let processor =
+            LineProcessor::new(detector, false, false).with_gap(SignedDuration::from_secs(30));
+
+        let input = concat!(
+            r#"{"timestamp":"2023-01-01T12:00:00Z","level":"info","message":"First"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:00:20Z","level":"info","message":"Second"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:04:32Z","level":"info","message":"Third"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:10:00Z","level":"info","message":"Starting"}"#,
+        );
+
+        let mut output = Vec::new();
+        processor
+            .process_lines(Cursor::new(input), &mut output)
+            .unwrap();
+        // a pause before a new session is a gap too
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                "12:00:00.000Z [inf] First\n",
+                "12:00:20.000Z [inf] Second\n",
+                "---- 4m12s gap ----\n",
+                "12:04:32.000Z [inf] Third\n",
+                "---- Session started 2023-01-01 ----\n",
+                "---- 5m28s gap ----\n",
+                "12:10:00.000Z [inf] Starting\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_process_lines_with_time_range() {
+        let detector = SessionStartDetector::from_message("Starting");
+        let processor = LineProcessor::new(detector, false, false).with_time_range(
+            TimeRange::default()
+                .with_since("12:00:01".parse().unwrap())
+                .with_until("12:00:03".parse().unwrap()),
+        );
+
+        let input = concat!(
+            r#"{"timestamp":"2023-01-01T12:00:00Z","level":"info","message":"Before"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:00:01Z","level":"info","message":"First"}"#,
+            "\n",
+            r#"{"level":"info","message":"No timestamp"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:00:02.999Z","level":"info","message":"Last"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:00:03Z","level":"info","message":"After"}"#,
+        );
+
+        let mut output = Vec::new();
+        processor
+            .process_lines(Cursor::new(input), &mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "12:00:01.000Z [inf] First\n12:00:02.999Z [inf] Last\n"
+        );
+    }
+
+    #[test]
+    fn test_process_file_lines_after_seeking_to_time_range() {
+        let path = std::env::temp_dir().join(format!("jl-pretty-{}-sessions", std::process::id()));
+        let start = "2023-01-01T00:00:00Z".parse::<Timestamp>().unwrap();
+        let content = (0..10_000)
+            .map(|idx| {
+                let ts = start + SignedDuration::from_secs(idx * 30);
+                let message = match idx % 5_000 {
+                    0 => "Starting".to_string(),
+                    _ => format!("Tick {idx}"),
+                };
+                format!(r#"{{"timestamp":"{ts}","level":"info","message":"{message}"}}"#) + "\n"
+            })
+            .collect::<String>();
+        std::fs::write(&path, &content).unwrap();
+
+        let detector = SessionStartDetector::from_message("Starting");
+        let processor = LineProcessor::new(detector, false, false).with_time_range(
+            TimeRange::default()
+                .with_since("2023-01-03T12:00:00Z".parse().unwrap())
+                .with_until("2023-01-03T12:01:00Z".parse().unwrap()),
+        );
+        let mut file = File::open(&path).unwrap();
+        let skipped = processor.seek_to_time_range(&path, &mut file).unwrap();
+        // the seek lands well after the start of the session
+        assert!(skipped.lines > 5_000, "{skipped:?}");
+
+        let mut output = Vec::new();
+        processor
+            .process_file_lines_after(&path, skipped, std::io::BufReader::new(file), &mut output)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // yet the banner of the session is shown
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                "---- Session started 2023-01-02 ----\n",
+                "12:00:00.000Z [inf] Tick 7200\n",
+                "12:00:30.000Z [inf] Tick 7201\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_process_lines_with_grep() {
+        let detector = SessionStartDetector::from_message("Starting");
+        let grep = Grep::default()
+            .with_message(regex::Regex::new("fail").unwrap())
+            .with_context(0, 1);
+        let processor = LineProcessor::new(detector, false, true).with_grep(grep);
+
+        let input = concat!(
+            r#"{"timestamp":"2023-01-01T12:00:00Z","level":"info","message":"It failed"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:00:01Z","level":"info","message":"Next"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:00:02Z","level":"info","message":"Skipped"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:00:03Z","level":"info","message":"Failed to fail"}"#,
+        );
+
+        let mut output = Vec::new();
+        processor
+            .process_lines(Cursor::new(input), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        let highlighted = concat!(
+            ansi_color!(bg: 94),
+            ansi_color!(fg: 230),
+            "fail",
+            ansi_color!(),
+            ansi_color!(fg: 254)
+        );
+        assert!(lines[0].ends_with(&format!("It {highlighted}ed{}", ansi_color!())));
+        assert!(lines[1].contains("Next"));
+        assert_eq!(lines[2], concat!(ansi_color!(fg: 240), "--", ansi_color!()));
+        assert!(lines[3].contains(&format!("Failed to {highlighted}")));
+    }
+
+    #[test]
+    fn test_process_lines_with_query() {
+        let detector = SessionStartDetector::from_message("Starting");
+        let query = r#"status >= 500 && path ~ "^/api""#.parse().unwrap();
+        let processor = LineProcessor::new(detector, false, false).with_query(query);
+
+        let input = concat!(
+            r#"{"timestamp":"2023-01-01T12:00:00Z","level":"error","message":"A","status":503,"path":"/api/a"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:00:01Z","level":"error","message":"B","status":503,"path":"/b"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:00:02Z","level":"info","message":"C","status":200,"path":"/api/c"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:00:03Z","level":"info","message":"D"}"#,
+        );
+
+        let mut output = Vec::new();
+        processor
+            .process_lines(Cursor::new(input), &mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "12:00:00.000Z [err] A\n"
+        );
+    }
+
+    #[test]
+    fn test_process_lines_with_level_filter() {
+        let processor = || {
+            let detector = SessionStartDetector::from_message("Starting");
+            LineProcessor::new(detector, false, false)
+        };
+
+        let input = concat!(
+            r#"{"timestamp":"2023-01-01T12:00:00Z","level":"info","message":"Starting"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:00:01Z","level":"error","message":"Failed"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:00:02Z","level":"debug","message":"Details"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-02T08:00:00Z","level":"info","message":"Starting"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-02T08:00:01Z","level":"info","message":"Ready"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-03T09:00:00Z","level":"info","message":"Starting"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-03T09:00:01Z","level":"warn","message":"Slow"}"#,
+        );
+        let process = |processor: LineProcessor| {
+            let mut output = Vec::new();
+            processor
+                .process_lines(Cursor::new(input), &mut output)
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        // the banner goes before the first line of the session shown, and a
+        // session with no lines shown has none
+        let level_filter = LevelFilter::all().with_min_level(Level::Warning);
+        assert_eq!(
+            process(processor().with_level_filter(level_filter)),
+            concat!(
+                "---- Session started 2023-01-01 ----\n",
+                "12:00:01.000Z [err] Failed\n",
+                "---- Session started 2023-01-03 ----\n",
+                "09:00:01.000Z [wrn] Slow\n",
+            )
+        );
+
+        // the same goes for lines left out by a grep
+        let grep = Grep::default().with_message(regex::Regex::new("Ready").unwrap());
+        assert_eq!(
+            process(processor().with_grep(grep)),
+            "---- Session started 2023-01-02 ----\n08:00:01.000Z [inf] Ready\n"
+        );
+    }
+
+    #[test]
+    fn test_process_file_lines_with_filename() {
+        let processor =
+            LineProcessor::new(SessionStartDetector::Never, false, false).with_filename(true);
+
+        let input =
+            r#"{"timestamp":"2023-01-01T12:00:00Z","level":"error","message":"Failed\nbadly"}"#;
+
+        let mut output = Vec::new();
+        processor
+            .process_file_lines(Path::new("logs/app.log"), Cursor::new(input), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "app.log: 12:00:00.000Z [err] Failed\n                             badly\n"
+        );
+    }
+
+    #[test]
+    fn test_process_file_lines_error_names_file() {
+        let processor = LineProcessor::new(SessionStartDetector::Never, false, false);
+
+        let input = "{\"message\":\"hi\"}\nnot json\n";
+
+        let mut output = Vec::new();
+        let err = processor
+            .process_file_lines(Path::new("app.log"), Cursor::new(input), &mut output)
+            .unwrap_err();
+        assert!(
+            matches!(&err, ProcessError::ParseError(Some(path), 2, 1, _) if path == Path::new("app.log"))
+        );
+        assert!(
+            err.to_string()
+                .starts_with("Parse error on line 2, column 1 of 'app.log': ")
+        );
+
+        // line numbers count from the start of the file, after skipping some
+        let skipped = Skipped {
+            bytes: 1234,
+            lines: 40,
+            session_start: None,
+        };
+        let err = processor
+            .process_file_lines_after(
+                Path::new("app.log"),
+                skipped,
+                Cursor::new(input),
+                &mut output,
+            )
+            .unwrap_err();
+        assert!(matches!(&err, ProcessError::ParseError(_, 42, 1, _)));
+    }
+
+    #[test]
+    fn test_process_lines_error_columns() {
+        let processor = LineProcessor::new(SessionStartDetector::Never, false, false);
+        let error = |input: &str| {
+            processor
+                .process_lines(Cursor::new(input.to_string()), &mut Vec::new())
+                .unwrap_err()
+                .to_string()
+        };
+
+        // columns count from 1, at the start of the line and within a record
+        assert_eq!(
+            error("xyz"),
+            "Parse error on line 1, column 1: invalid JSON"
+        );
+        assert_eq!(
+            error("{\"message\":\"hi\"}\n{\"a\":1,x}"),
+            "Parse error on line 2, column 8: invalid JSON"
+        );
+
+        // and from the start of the line, before any prefix
+        let processor = processor.with_prefix(LinePrefix::Cri);
+        let line = r#"2025-04-10T20:25:01Z stdout F {"a":1,x}"#;
+        let column = line.find('x').unwrap() + 1;
+        assert_eq!(
+            processor
+                .process_lines(Cursor::new(line), &mut Vec::new())
+                .unwrap_err()
+                .to_string(),
+            format!("Parse error on line 1, column {column}: invalid JSON")
+        );
+    }
+
+    #[test]
+    fn test_process_merged() {
+        let processor = LineProcessor::new(SessionStartDetector::Never, false, false);
+
+        let api = concat!(
+            r#"{"timestamp":"2023-01-01T12:00:01Z","level":"info","message":"Request"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:00:04Z","level":"info","message":"Response"}"#,
+        );
+        let db = concat!(
+            r#"{"timestamp":"2023-01-01T12:00:03Z","level":"debug","message":"Commit"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:00:02Z","level":"debug","message":"Query"}"#,
+        );
+
+        let mut output = Vec::new();
+        processor
+            .process_merged(
+                vec![
+                    (Path::new("api.log"), Cursor::new(api)),
+                    (Path::new("db-server.log"), Cursor::new(db)),
+                ],
+                &mut output,
+            )
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            concat!(
+                "api.log:       12:00:01.000Z [inf] Request\n",
+                "db-server.log: 12:00:02.000Z [dbg] Query\n",
+                "db-server.log: 12:00:03.000Z [dbg] Commit\n",
+                "api.log:       12:00:04.000Z [inf] Response\n",
+            )
+        );
+    }
+
+    /// Builds a random, mostly JSON, log line from awkward pieces
+    fn random_line(state: &mut u64) -> String {
+        const TIMESTAMPS: [&str; 12] = [
+            r#""""#,
+            r#""2""#,
+            r#""2025-01-01T1""#,
+            r#""2025-01-01Tæøå""#,
+            r#""2025-04-10T20:25:01.133+02:00""#,
+            r#""2025-04-10 20:25:01""#,
+            "1e400",
+            "-1",
+            "99999999999999999999999999",
+            "1744316701.133",
+            "null",
+            r#"{"nested":true}"#,
+        ];
+        const LEVELS: [&str; 6] = [r#""info""#, r#""WARN""#, "30", "-1", r#""""#, "[]"];
+        const MESSAGES: [&str; 6] = [
+            r#""plain""#,
+            r#""multi\nline\r\n""#,
+            r#""æøå \"quoted\"""#,
+            r#""\n\n""#,
+            r#""Error: boom\n    at f (/app/node_modules/x.js:1:1)\n    at g (/app/a.js:2:2)""#,
+            "12.5",
+        ];
+        let mut pick =
+            |options: &[&'static str]| options[(xorshift(state) % options.len() as u64) as usize];
+        let line = format!(
+            r#"{{"timestamp":{},"level":{},"message":{},"stack":{},"extra":{}}}"#,
+            pick(&TIMESTAMPS),
+            pick(&LEVELS),
+            pick(&MESSAGES),
+            pick(&MESSAGES),
+            pick(&TIMESTAMPS),
+        );
+        // every so often, cut the line short at a random (char) position
+        match xorshift(state) % 5 {
+            0 => {
+                let cut = (xorshift(state) % line.len() as u64) as usize;
+                let cut = (0..=cut).rev().find(|i| line.is_char_boundary(*i)).unwrap();
+                line[..cut].to_string()
+            }
+            _ => line,
+        }
+    }
+
+    #[test]
+    fn test_fuzz_process_lines_never_panics() {
+        let mut state = 0x9e37_79b9_7f4a_7c15;
+        let input = (0..500)
+            .map(|_| random_line(&mut state))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        // invalid lines are shown rather than skipped, which would report
+        // each of them on std err
+        let processors = [
+            LineProcessor::new(SessionStartDetector::from_message("plain"), false, false),
+            LineProcessor::new(SessionStartDetector::Never, false, true)
+                .with_collapse_stack(true)
+                .with_extra_fields(ExtraFields::all().with_nested(crate::NestedStyle::Pretty)),
+            LineProcessor::new(SessionStartDetector::Never, false, false)
+                .with_format_detection(10)
+                .with_time_format(
+                    TimeFormat::default()
+                        .with_date(true)
+                        .with_zone(crate::timestamp::time_zone("Europe/Copenhagen").unwrap()),
+                ),
+            LineProcessor::new(SessionStartDetector::Never, false, false)
+                .with_schema(crate::Format::Pino.schema())
+                .with_level_filter(LevelFilter::all().with_min_level(Level::Warning)),
+        ]
+        .map(|processor| processor.with_invalid_lines(InvalidLines::Raw));
+        for processor in processors {
+            let mut output = Vec::new();
+            processor
+                .process_lines(Cursor::new(&input), &mut output)
+                .unwrap();
+            processor
+                .process_merged(
+                    vec![
+                        (Path::new("a.log"), Cursor::new(&input)),
+                        (Path::new("b.log"), Cursor::new(&input)),
+                    ],
+                    &mut output,
+                )
+                .unwrap();
+        }
+    }
+
+    /// Process a single line with the default settings, and return the output
+    fn process_single_line(input: &str) -> String {
+        let processor = LineProcessor::new(SessionStartDetector::Never, false, false);
+        let mut output = Vec::new();
+        processor
+            .process_lines(Cursor::new(input), &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_process_lines_with_escaped_quotes() {
+        let output = process_single_line(
+            r#"{"timestamp":"2023-01-01T12:00:00Z","level":"info","message":"Say \"hello\" to \\\\server"}"#,
+        );
+        assert_eq!(output, "12:00:00.000Z [inf] Say \"hello\" to \\\\server\n");
+    }
+
+    #[test]
+    fn test_process_lines_with_escaped_newlines() {
+        let output = process_single_line(
+            r#"{"timestamp":"2023-01-01T12:00:00Z","level":"error","message":"First\nSecond\tTabbed"}"#,
+        );
+        assert_eq!(
+            output,
+            "12:00:00.000Z [err] First\n                    Second\tTabbed\n"
+        );
+    }
+
+    #[test]
+    fn test_process_lines_with_unicode_escapes() {
+        let output = process_single_line(
+            r#"{"timestamp":"2023-01-01T12:00:00Z","level":"info","message":"Caf\u00e9 \ud83d\ude80 \u2713"}"#,
+        );
+        assert_eq!(output, "12:00:00.000Z [inf] Café 🚀 ✓\n");
+    }
+
+    #[test]
+    fn test_process_lines_with_escaped_level_and_timestamp() {
+        let output = process_single_line(
+            r#"{"timestamp":"2023-01-01\u005412:00:00Z","level":"w\u0061rn","message":"Test message"}"#,
+        );
+        assert_eq!(output, "12:00:00.000Z [wrn] Test message\n");
+    }
+
+    const STACK_LINE: &str = r#"{"timestamp":"2023-01-01T12:00:00.123Z","level":"error","message":"Request failed","stack":"Error: boom\n    at handler (/app/index.js:10:5)\n    at Layer.handle (/app/node_modules/express/lib/router/layer.js:95:5)\n    at next (/app/node_modules/express/lib/router/route.js:149:13)\n"}"#;
+
+    #[test]
+    fn test_process_lines_with_stack() {
+        let output = process_single_line(STACK_LINE);
+        assert_eq!(
+            output,
+            concat!(
+                "12:00:00.123Z [err] Request failed\n",
+                "                    Error: boom\n",
+                "                        at handler (/app/index.js:10:5)\n",
+                "                        at Layer.handle (/app/node_modules/express/lib/router/layer.js:95:5)\n",
+                "                        at next (/app/node_modules/express/lib/router/route.js:149:13)\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_process_lines_with_collapsed_stack() {
+        let processor =
+            LineProcessor::new(SessionStartDetector::Never, false, false).with_collapse_stack(true);
+        let mut output = Vec::new();
+        processor
+            .process_lines(Cursor::new(STACK_LINE), &mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                "12:00:00.123Z [err] Request failed\n",
+                "                    Error: boom\n",
+                "                        at handler (/app/index.js:10:5)\n",
+                "                        ... 2 library frames\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_process_lines_with_stack_colors() {
+        let processor = LineProcessor::new(SessionStartDetector::Never, false, true);
+        let mut output = Vec::new();
+        processor
+            .process_lines(Cursor::new(STACK_LINE), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[1],
+            concat!(
+                "                    ",
+                ansi_color!(fg: 9),
+                "Error: boom",
+                ansi_color!()
+            )
+        );
+        assert!(lines[2].starts_with(concat!("                    ", ansi_color!(fg: 244))));
+    }
+
+    const INVALID_LINES_SOURCE: &str = include_str!("../../test-data/log-with-invalid-lines.jsonl");
+
+    #[test]
+    fn test_process_lines_with_invalid_lines_skip() {
+        let detector = SessionStartDetector::Never;
+        let processor = LineProcessor::new(detector, true, false);
+
+        let mut output = Vec::new();
+
+        processor
+            .process_lines(Cursor::new(INVALID_LINES_SOURCE), &mut output)
+            .unwrap();
+
+        let output_lines = Cursor::new(output)
+            .lines()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(output_lines.len(), 3);
+        assert_eq!(output_lines[0], "20:25:01.133Z [inf] First valid line");
+        assert_eq!(output_lines[1], "20:26:01.133Z [wrn] Second valid line");
+        assert_eq!(output_lines[2], "20:27:01.133Z [inf] Third valid line");
+    }
+
+    #[test]
+    fn test_process_lines_with_invalid_lines_raw() {
+        let input = concat!(
+            r#"{"timestamp":"2023-01-01T12:00:00Z","level":"info","message":"Starting"}"#,
+            "\n",
+            "panic: runtime error\n",
+            r#"{"timestamp":"2023-01-01T12:00:01Z","level":"debug","message":"Hidden"}"#,
+            "\n",
+            "  goroutine 1 [running]\n",
+            r#"{"timestamp":"2023-01-01T12:00:02Z","level":"debug","message":"Hidden"}"#,
+            "\n",
+            "panic: boom\n",
+        );
+        let process = |processor: LineProcessor| {
+            let mut output = Vec::new();
+            processor
+                .with_level_filter(LevelFilter::all().with_min_level(Level::Info))
+                .process_lines(Cursor::new(input), &mut output)
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        // the raw lines are shown, even after lines left out by level
+        let processor = LineProcessor::new(SessionStartDetector::Never, false, false)
+            .with_invalid_lines(InvalidLines::Raw);
+        assert_eq!(
+            process(processor),
+            concat!(
+                "12:00:00.000Z [inf] Starting\n",
+                "panic: runtime error\n",
+                "  goroutine 1 [running]\n",
+                "panic: boom\n",
+            )
+        );
+
+        let processor = LineProcessor::new(SessionStartDetector::Never, false, true)
+            .with_invalid_lines(InvalidLines::Dim);
+        assert!(process(processor).ends_with(concat!(
+            ansi_color!(fg: 242),
+            "panic: boom",
+            ansi_color!(),
+            "\n"
+        )));
+    }
+
+    #[test]
+    fn test_process_lines_with_prefixes() {
+        let input = concat!(
+            r#"2023-01-01T12:00:00Z stderr P {"timestamp":"2023-01-01T12:00:00Z","level":"#,
+            "\n",
+            r#"2023-01-01T12:00:00Z stderr F "error","message":"Failed\nbadly"}"#,
+            "\n",
+            r#"2023-01-01T12:00:01Z stdout F {"timestamp":"2023-01-01T12:00:01Z","level":"info","message":"Up"}"#,
+            "\n",
+        );
+        let process = |processor: LineProcessor| {
+            let mut output = Vec::new();
+            processor
+                .with_format_detection(10)
+                .process_lines(Cursor::new(input), &mut output)
+                .map(|_| String::from_utf8(output).unwrap())
+        };
+
+        let processor = LineProcessor::new(SessionStartDetector::Never, false, false);
+        assert_eq!(
+            process(processor).unwrap(),
+            concat!(
+                "[stderr] 12:00:00.000Z [err] Failed\n",
+                "                             badly\n",
+                "[stdout] 12:00:01.000Z [inf] Up\n",
+            )
+        );
+
+        let processor = LineProcessor::new(SessionStartDetector::Never, false, false)
+            .with_prefix(LinePrefix::Off);
+        assert!(process(processor).is_err());
+    }
+
+    #[test]
+    fn test_process_lines_when_following() {
+        /// Fails to read, like a followed file would wait for more lines
+        struct Waiting;
+        impl std::io::Read for Waiting {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::WouldBlock.into())
+            }
+        }
+        let input = concat!(
+            r#"{"timestamp":"2023-01-01T12:00:00Z","level":"info","message":"First"}"#,
+            "\n",
+            r#"{"timestamp":"2023-01-01T12:00:01Z","level":"info","message":"Second"}"#,
+            "\n",
+        );
+        let process = |processor: LineProcessor| {
+            let mut output = Vec::new();
+            let reader = std::io::BufReader::new(std::io::Read::chain(Cursor::new(input), Waiting));
+            let result = processor
+                .with_format_detection(10)
+                .process_lines(reader, &mut output);
+            (result, String::from_utf8(output).unwrap())
+        };
+
+        // reading ahead for the format waits before anything is shown
+        let processor = LineProcessor::new(SessionStartDetector::Never, false, false);
+        let (result, output) = process(processor);
+        assert!(result.is_err());
+        assert_eq!(output, "");
+
+        // unless we are following, where the lines are shown with the schema
+        // given up front as they come
+        let processor =
+            LineProcessor::new(SessionStartDetector::Never, false, false).with_follow(true);
+        let (result, output) = process(processor);
+        assert!(result.is_err());
+        assert_eq!(
+            output,
+            "12:00:00.000Z [inf] First\n12:00:01.000Z [inf] Second\n"
+        );
+    }
+
+    #[test]
+    fn test_process_lines_with_journald_format() {
+        let entry = |micros: &str, priority: &str, message: &str| {
+            format!(
+                r#"{{"__REALTIME_TIMESTAMP":"{micros}","PRIORITY":"{priority}","_HOSTNAME":"web01","SYSLOG_IDENTIFIER":"api","MESSAGE":{message}}}"#
+            )
+        };
+        let input = [
+            entry(
+                "1672574400000000",
+                "6",
+                r#""{\"time\":1672574400000,\"level\":40,\"msg\":\"Slow\"}""#,
+            ),
+            entry("1672574401000000", "3", r#""Disk full""#),
+        ]
+        .join("\n");
+        let process = |processor: LineProcessor| {
+            let mut output = Vec::new();
+            processor
+                .with_format(Format::Journald)
+                .process_lines(Cursor::new(input.clone()), &mut output)
+                .map(|_| String::from_utf8(output).unwrap())
+        };
+
+        // the messages are unwrapped, and their format (pino) detected
+        let processor = LineProcessor::new(SessionStartDetector::Never, false, false);
+        assert_eq!(
+            process(processor).unwrap(),
+            concat!(
+                "[web01 api] 12:00:00.000Z [wrn] Slow\n",
+                "[web01 api] 12:00:01.000Z [err] Disk full\n",
+            )
+        );
+
+        // unless the entries are to be read as they are
+        let processor = LineProcessor::new(SessionStartDetector::Never, false, false)
+            .with_prefix(LinePrefix::Off);
+        assert_eq!(
+            process(processor).unwrap(),
+            concat!(
+                "12:00:00.000Z [inf] {\"time\":1672574400000,\"level\":40,\"msg\":\"Slow\"}\n",
+                "12:00:01.000Z [err] Disk full\n",
+            )
+        );
+
+        // messages without a level of their own get the level of the
+        // priority, whatever the format of the other messages
+        let input = [
+            entry("1672574400000000", "2", r#""Critical""#),
+            entry("1672574401000000", "5", r#""Notice""#),
+            entry(
+                "1672574402000000",
+                "7",
+                r#""{\"time\":1672574402000,\"msg\":\"No level\"}""#,
+            ),
+        ];
+        let expected = [
+            "[web01 api] 12:00:00.000Z [err] Critical\n",
+            "[web01 api] 12:00:01.000Z [inf] Notice\n",
+            "[web01 api] 12:00:02.000Z [dbg] No level\n",
+        ];
+        // the first two alone leave the fallback schema, the third makes it pino
+        for count in [2, 3] {
+            let mut output = Vec::new();
+            LineProcessor::new(SessionStartDetector::Never, false, false)
+                .with_format(Format::Journald)
+                .process_lines(Cursor::new(input[..count].join("\n")), &mut output)
+                .unwrap();
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                expected[..count].concat()
+            );
+        }
+    }
+
+    #[test]
+    fn test_process_lines_with_json_framing() {
+        let input = concat!(
+            "{\n",
+            "  \"timestamp\": \"2023-01-01T12:00:00Z\",\n",
+            "  \"level\": \"info\",\n",
+            "  \"message\": \"Pretty\"\n",
+            "}",
+            r#"{"timestamp":"2023-01-01T12:00:01Z","level":"warn","message":"Packed"}"#,
+            "\n{\n  \"level\": \"info\"\n  \"message\": \"Broken\"\n}\n",
+        );
+        let processor = LineProcessor::new(SessionStartDetector::Never, false, false)
+            .with_framing(Framing::Json);
+
+        let mut output = Vec::new();
+        let err = processor
+            .process_lines(Cursor::new(input), &mut output)
+            .unwrap_err();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "12:00:00.000Z [inf] Pretty\n12:00:01.000Z [wrn] Packed\n"
+        );
+        // the position of the error is in the input, not in the value
+        assert!(matches!(err, ProcessError::ParseError(None, 6, 1, _)));
+        assert!(
+            err.to_string()
+                .starts_with("Parse error on line 8, column 3: invalid JSON")
+        );
+
+        let input = concat!(
+            r#"{"timestamp":"2023-01-01T12:00:00Z","level":"info","message":"A"} "#,
+            r#"{"timestamp":"2023-01-01T12:00:01Z","level":"info","message":}"#,
+        );
+        let err = processor
+            .process_lines(Cursor::new(input), &mut Vec::new())
+            .unwrap_err();
+        assert!(matches!(err, ProcessError::ParseError(None, 1, 67, _)));
+        assert!(
+            err.to_string()
+                .starts_with("Parse error on line 1, column 128: invalid JSON")
+        );
+    }
+
+    #[test]
+    fn test_process_lines_with_json_framing_and_raw_lines() {
+        let input = concat!(
+            "Starting up, please wait\n",
+            "{\n  \"timestamp\": \"2023-01-01T12:00:00Z\",\n  \"level\": \"info\",\n  \"message\": \"Up\"\n}\n",
+            "panic: runtime error\n",
+        );
+        let processor = LineProcessor::new(SessionStartDetector::Never, false, false)
+            .with_framing(Framing::Json)
+            .with_invalid_lines(InvalidLines::Raw);
+
+        // plain text is shown a line at a time, not a word at a time
+        let mut output = Vec::new();
+        processor
+            .process_lines(Cursor::new(input), &mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                "Starting up, please wait\n",
+                "12:00:00.000Z [inf] Up\n",
+                "panic: runtime error\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_process_lines_with_json_array() {
+        let input = concat!(
+            "[\n",
+            r#"  {"time":1672574400000,"level":30,"msg":"First"},"#,
+            "\n",
+            r#"  {"time":1672574401000,"level":40,"msg":"Second"}"#,
+            "\n]\n",
+        );
+        let processor =
+            LineProcessor::new(SessionStartDetector::Never, false, false).with_format_detection(10);
+
+        let mut output = Vec::new();
+        processor
+            .process_lines(Cursor::new(input), &mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "12:00:00.000Z [inf] First\n12:00:01.000Z [wrn] Second\n"
+        );
     }
 
     #[test]
@@ -258,9 +2234,18 @@ mod tests {
         let detector = SessionStartDetector::Never;
         let processor = LineProcessor::new(detector, false, false);
 
-        assert_eq!(processor.get_level_label("info"), " [inf] ");
-        assert_eq!(processor.get_level_label("error"), " [err] ");
-        assert_eq!(processor.get_level_label("unknown"), " [unk] ");
+        assert_eq!(
+            processor.get_level_label(processor.schema.level("info")),
+            " [inf] "
+        );
+        assert_eq!(
+            processor.get_level_label(processor.schema.level("error")),
+            " [err] "
+        );
+        assert_eq!(
+            processor.get_level_label(processor.schema.level("unknown")),
+            " [unk] "
+        );
     }
 
     #[test]
@@ -269,15 +2254,15 @@ mod tests {
         let processor = LineProcessor::new(detector, false, true);
 
         assert_eq!(
-            processor.get_level_label("info"),
+            processor.get_level_label(processor.schema.level("info")),
             concat!(ansi_color!(fg: 254), " [inf] ")
         );
         assert_eq!(
-            processor.get_level_label("error"),
+            processor.get_level_label(processor.schema.level("error")),
             concat!(ansi_color!(fg: 9), " [err] ")
         );
         assert_eq!(
-            processor.get_level_label("unknown"),
+            processor.get_level_label(processor.schema.level("unknown")),
             concat!(ansi_color!(fg: 5), " [unk] ")
         );
     }
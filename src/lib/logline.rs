@@ -1,28 +1,250 @@
-use sonic_rs::Deserialize;
+use std::borrow::Cow;
 
-#[derive(Deserialize)]
+use serde::de::Error as _;
+use sonic_rs::{JsonValueTrait, LazyValue};
+
+use crate::fields::FieldMap;
+
+/// A single parsed log line.
+///
+/// The fields borrow from the line they were parsed from, except for strings
+/// with escape sequences in them, which need to be unescaped into owned
+/// copies.
+///
 pub struct LogLine<'a> {
-    timestamp: &'a str,
-    level: &'a str,
-    message: &'a str,
+    source: &'a str,
+    timestamp: Cow<'a, str>,
+    level: Cow<'a, str>,
+    message: Cow<'a, str>,
+    stack: Cow<'a, str>,
 }
 
 impl<'a> LogLine<'a> {
     pub fn from_message(message: &'a str) -> Self {
         Self {
-            timestamp: "2025-01-01T12:00:00.345Z",
-            level: "info",
+            source: "",
+            timestamp: Cow::Borrowed("2025-01-01T12:00:00.345Z"),
+            level: Cow::Borrowed("info"),
+            message: Cow::Borrowed(message),
+            stack: Cow::Borrowed(""),
+        }
+    }
+
+    /// Parses a single JSON log line, picking out the timestamp, level and
+    /// message using the paths in `fields`. Fields missing from the line are
+    /// left empty.
+    pub fn parse(line: &'a str, fields: &FieldMap) -> sonic_rs::Result<Self> {
+        // sonic-rs does not expect to be handed blank input (its index
+        // arithmetic underflows)
+        if line.trim_ascii().is_empty() {
+            return Err(sonic_rs::Error::custom(
+                "expected a JSON object, found a blank line",
+            ));
+        }
+        // a single pass over the fields of the object, which checks that it
+        // is valid JSON as it goes, keeping the value of the first path found
+        // for each of our fields
+        let paths = [
+            fields.timestamp(),
+            fields.level(),
+            fields.message(),
+            fields.stack(),
+        ];
+        // the index of the path each field was found at, and its text
+        let mut found = [const { (usize::MAX, Cow::Borrowed("")) }; 4];
+        let mut end = line.find('{').map_or(0, |start| start + 1);
+        for field in sonic_rs::to_object_iter(line) {
+            let (key, value) = field?;
+            for (paths, (found_idx, text)) in paths.iter().zip(&mut found) {
+                let tried = paths.len().min(*found_idx);
+                let better = paths[..tried]
+                    .iter()
+                    .enumerate()
+                    .find_map(|(idx, path)| Some((idx, path.lookup_field(line, &key, &value)?)));
+                if let Some((idx, value)) = better {
+                    *found_idx = idx;
+                    *text = value_str(value)?;
+                }
+            }
+            end = end_offset(line, value.as_raw_str());
+        }
+        // the iteration stops at the closing brace, without looking past it
+        if line[end..].trim_ascii() != "}" {
+            return Err(sonic_rs::Error::custom(
+                "unexpected characters after the JSON object",
+            ));
+        }
+        // an object with neither a timestamp nor a message (i.e. '{}', or a
+        // line of another format) has nothing to show
+        let [(timestamp_idx, _), _, (message_idx, _), _] = &found;
+        if *timestamp_idx == usize::MAX && *message_idx == usize::MAX {
+            return Err(sonic_rs::Error::custom(
+                "expected a log line, found no timestamp or message",
+            ));
+        }
+
+        let [timestamp, level, message, stack] = found.map(|(_, text)| text);
+        Ok(Self {
+            source: line,
+            timestamp,
+            level,
             message,
+            stack,
+        })
+    }
+
+    /// A line of plain text, with the timestamp given by whatever wrapped it
+    /// (i.e. the docker log driver). It has no other fields.
+    pub(crate) fn plain(timestamp: &'a str, message: &'a str) -> Self {
+        Self {
+            source: "{}",
+            timestamp: Cow::Borrowed(timestamp),
+            level: Cow::Borrowed(""),
+            message: Cow::Borrowed(message),
+            stack: Cow::Borrowed(""),
+        }
+    }
+
+    /// Uses `timestamp` as the timestamp of the line, if it has none of its
+    /// own
+    pub(crate) fn with_fallback_timestamp(mut self, timestamp: &'a str) -> Self {
+        if self.timestamp.is_empty() {
+            self.timestamp = Cow::Borrowed(timestamp);
         }
+        self
     }
 
-    pub fn timestamp(&self) -> &'a str {
-        self.timestamp
+    /// The JSON text the line was parsed from
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+    pub fn timestamp(&self) -> &str {
+        &self.timestamp
+    }
+    pub fn level(&self) -> &str {
+        &self.level
     }
-    pub fn level(&self) -> &'a str {
-        self.level
+    pub fn message(&self) -> &str {
+        &self.message
     }
-    pub fn message(&self) -> &'a str {
-        self.message
+    pub fn stack(&self) -> &str {
+        &self.stack
+    }
+}
+
+/// Checks if the given line is a (valid) JSON object
+pub(crate) fn is_json_object(line: &str) -> bool {
+    !line.trim_ascii().is_empty()
+        && sonic_rs::from_str::<LazyValue>(line).is_ok_and(|root| root.is_object())
+}
+
+/// The text of a field, where strings are returned without quotes, other
+/// values as their JSON text, and null as nothing
+fn value_str(value: LazyValue<'_>) -> sonic_rs::Result<Cow<'_, str>> {
+    if value.is_null() {
+        return Ok(Cow::Borrowed(""));
+    }
+    let is_str = value.is_str();
+    match value.as_raw_cow() {
+        Cow::Borrowed(raw) => raw_text(raw, is_str),
+        Cow::Owned(raw) => raw_text(&raw, is_str).map(|text| Cow::Owned(text.into_owned())),
+    }
+}
+
+/// The offset of the end of `part` in `line`, which it is a slice of
+fn end_offset(line: &str, part: &str) -> usize {
+    part.as_ptr() as usize - line.as_ptr() as usize + part.len()
+}
+
+/// The text of a raw JSON value, i.e. strings without quotes
+fn raw_text(raw: &str, is_str: bool) -> sonic_rs::Result<Cow<'_, str>> {
+    if !is_str {
+        Ok(Cow::Borrowed(raw))
+    } else if raw.contains('\\') {
+        // the slow path, unescaping needs a copy
+        sonic_rs::from_str::<String>(raw).map(Cow::Owned)
+    } else {
+        // the fast path, just strip the quotes
+        Ok(Cow::Borrowed(&raw[1..raw.len() - 1]))
+    }
+}
+
+// --------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fields::paths;
+
+    #[test]
+    fn test_parse_default_fields() {
+        let line = r#"{"timestamp":"2025-01-01T12:00:00Z","level":"info","message":"hi"}"#;
+        let log_line = LogLine::parse(line, &FieldMap::default()).unwrap();
+        assert_eq!(log_line.timestamp(), "2025-01-01T12:00:00Z");
+        assert_eq!(log_line.level(), "info");
+        assert_eq!(log_line.message(), "hi");
+    }
+
+    #[test]
+    fn test_parse_fallback_and_nested_fields() {
+        let fields = FieldMap::default()
+            .with_timestamp(paths(&["time", "ts"]))
+            .with_level(paths(&["log.level"]))
+            .with_message(paths(&["msg"]));
+        let line = r#"{"ts":"2025-01-01T12:00:00Z","log":{"level":"warn"},"msg":"hi"}"#;
+        let log_line = LogLine::parse(line, &fields).unwrap();
+        assert_eq!(log_line.timestamp(), "2025-01-01T12:00:00Z");
+        assert_eq!(log_line.level(), "warn");
+        assert_eq!(log_line.message(), "hi");
+
+        // the first path found wins, wherever it is in the line, and nested
+        // paths are found as flattened keys too
+        let line =
+            r#"{"ts":"2025-01-01T12:00:00Z","log.level":"warn","time":"2025-01-01T12:00:01Z"}"#;
+        let log_line = LogLine::parse(line, &fields).unwrap();
+        assert_eq!(log_line.timestamp(), "2025-01-01T12:00:01Z");
+        assert_eq!(log_line.level(), "warn");
+    }
+
+    #[test]
+    fn test_parse_missing_and_non_string_fields() {
+        let line = r#"{"level":30,"message":null}"#;
+        let log_line = LogLine::parse(line, &FieldMap::default()).unwrap();
+        assert_eq!(log_line.timestamp(), "");
+        assert_eq!(log_line.level(), "30");
+        assert_eq!(log_line.message(), "");
+    }
+
+    #[test]
+    fn test_parse_rejects_lines_without_fields() {
+        // an unrelated object, or a line of another format, has neither a
+        // timestamp nor a message to show
+        assert!(LogLine::parse(r#"{"foo":1}"#, &FieldMap::default()).is_err());
+        let line = r#"{"level":30,"time":1744316701133,"msg":"hi"}"#;
+        assert!(LogLine::parse(line, &FieldMap::default()).is_err());
+        let fields = FieldMap::default().with_message(paths(&["msg"]));
+        assert!(LogLine::parse(line, &fields).is_ok());
+    }
+
+    #[test]
+    fn test_parse_borrows_unless_escaped() {
+        let line = r#"{"level":"info","message":"say \"hi\""}"#;
+        let log_line = LogLine::parse(line, &FieldMap::default()).unwrap();
+        assert!(matches!(log_line.level, Cow::Borrowed("info")));
+        assert!(matches!(log_line.message, Cow::Owned(_)));
+        assert_eq!(log_line.message(), r#"say "hi""#);
+    }
+
+    #[test]
+    fn test_parse_rejects_non_objects() {
+        assert!(LogLine::parse("[1,2,3]", &FieldMap::default()).is_err());
+        assert!(LogLine::parse(r#"{"level":"info""#, &FieldMap::default()).is_err());
+        assert!(LogLine::parse("", &FieldMap::default()).is_err());
+        assert!(LogLine::parse(" \t", &FieldMap::default()).is_err());
+        assert!(LogLine::parse(r#"{"level":"info"} {}"#, &FieldMap::default()).is_err());
+        assert!(LogLine::parse(r#"{"level":"info","n":01}"#, &FieldMap::default()).is_err());
+        assert!(LogLine::parse("{}", &FieldMap::default()).is_err());
+        assert!(LogLine::parse(" { } ", &FieldMap::default()).is_err());
+        assert!(!is_json_object(""));
     }
 }
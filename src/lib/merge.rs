@@ -0,0 +1,202 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    sync::mpsc::{Receiver, SyncSender},
+};
+
+use crate::framing::Position;
+
+// --------------------------------------------------------------------------
+
+/// A line from one of the inputs being merged, ordered by its timestamp and
+/// then by its position in the input
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct MergeLine {
+    /// The timestamp of the line, in nanoseconds since the Unix epoch
+    pub key: i128,
+    pub pos: Position,
+    pub line: String,
+}
+
+/// Restores the order of lines that are slightly out of order in an input, by
+/// holding back up to `capacity` lines and always releasing the earliest one.
+pub(crate) struct ReorderBuffer {
+    heap: BinaryHeap<Reverse<MergeLine>>,
+    capacity: usize,
+}
+
+impl ReorderBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            heap: BinaryHeap::with_capacity(capacity + 1),
+            capacity,
+        }
+    }
+
+    /// Adds a line, and returns the earliest line held if the buffer is full
+    pub fn push(&mut self, line: MergeLine) -> Option<MergeLine> {
+        self.heap.push(Reverse(line));
+        if self.heap.len() > self.capacity {
+            self.heap.pop().map(|Reverse(line)| line)
+        } else {
+            None
+        }
+    }
+
+    /// Releases the earliest line held, if any
+    pub fn pop(&mut self) -> Option<MergeLine> {
+        self.heap.pop().map(|Reverse(line)| line)
+    }
+}
+
+/// The sending end of the channel of an input being merged
+pub(crate) type MergeSender = SyncSender<crate::Result<MergeLine>>;
+
+/// Sends the lines of an input on to the merge, through a reorder buffer.
+/// Returns `false` if the merge has stopped listening.
+pub(crate) fn send_reordered<I>(lines: I, capacity: usize, tx: &MergeSender) -> bool
+where
+    I: Iterator<Item = crate::Result<MergeLine>>,
+{
+    let mut buffer = ReorderBuffer::new(capacity);
+    for line in lines {
+        let released = match line {
+            Ok(line) => buffer.push(line).map(Ok),
+            Err(err) => {
+                // the lines before the error are still good
+                while let Some(line) = buffer.pop() {
+                    if tx.send(Ok(line)).is_err() {
+                        return false;
+                    }
+                }
+                return tx.send(Err(err)).is_ok();
+            }
+        };
+        if let Some(line) = released
+            && tx.send(line).is_err()
+        {
+            return false;
+        }
+    }
+    while let Some(line) = buffer.pop() {
+        if tx.send(Ok(line)).is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Merges the (ordered) lines received from each of the inputs into a single
+/// stream in timestamp order, and passes each line to `emit` along with the
+/// index of its input. On equal timestamps, the earlier input goes first.
+pub(crate) fn merge<F>(
+    inputs: Vec<Receiver<crate::Result<MergeLine>>>,
+    mut emit: F,
+) -> crate::Result<()>
+where
+    F: FnMut(usize, MergeLine) -> crate::Result<()>,
+{
+    // the next line of each input, ordered by (timestamp, input, line no)
+    let mut heads = BinaryHeap::with_capacity(inputs.len());
+    let next = |idx: usize| -> crate::Result<Option<Reverse<(i128, usize, MergeLine)>>> {
+        match inputs[idx].recv() {
+            Ok(Ok(line)) => Ok(Some(Reverse((line.key, idx, line)))),
+            Ok(Err(err)) => Err(err),
+            // the input has ended
+            Err(_) => Ok(None),
+        }
+    };
+
+    for idx in 0..inputs.len() {
+        heads.extend(next(idx)?);
+    }
+    while let Some(Reverse((_, idx, line))) = heads.pop() {
+        emit(idx, line)?;
+        heads.extend(next(idx)?);
+    }
+    Ok(())
+}
+
+// --------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::sync_channel;
+
+    use super::*;
+    use crate::ProcessError;
+
+    fn line(key: i128, line_idx: usize) -> MergeLine {
+        MergeLine {
+            key,
+            pos: Position::line(line_idx),
+            line: format!("{key}"),
+        }
+    }
+
+    /// Feeds the given keys through the reorder buffer and a channel
+    fn input(keys: &[i128], capacity: usize) -> Receiver<crate::Result<MergeLine>> {
+        let (tx, rx) = sync_channel(keys.len() + 1);
+        let lines = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| Ok(line(*key, i + 1)));
+        assert!(send_reordered(lines, capacity, &tx));
+        rx
+    }
+
+    fn merged_keys(inputs: Vec<Receiver<crate::Result<MergeLine>>>) -> Vec<(usize, i128)> {
+        let mut keys = Vec::new();
+        merge(inputs, |idx, line| {
+            keys.push((idx, line.key));
+            Ok(())
+        })
+        .unwrap();
+        keys
+    }
+
+    #[test]
+    fn test_reorder_buffer() {
+        let mut buffer = ReorderBuffer::new(2);
+        assert_eq!(buffer.push(line(3, 1)), None);
+        assert_eq!(buffer.push(line(1, 2)), None);
+        assert_eq!(buffer.push(line(2, 3)), Some(line(1, 2)));
+        assert_eq!(buffer.pop(), Some(line(2, 3)));
+        assert_eq!(buffer.pop(), Some(line(3, 1)));
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn test_merge_in_timestamp_order() {
+        let keys = merged_keys(vec![input(&[1, 4, 6], 0), input(&[2, 3, 6, 7], 0)]);
+        assert_eq!(
+            keys,
+            [(0, 1), (1, 2), (1, 3), (0, 4), (0, 6), (1, 6), (1, 7)]
+        );
+    }
+
+    #[test]
+    fn test_merge_slightly_out_of_order_lines() {
+        let keys = merged_keys(vec![input(&[1, 5, 3, 4, 9], 2), input(&[2, 6], 2)]);
+        assert_eq!(
+            keys,
+            [(0, 1), (1, 2), (0, 3), (0, 4), (0, 5), (1, 6), (0, 9)]
+        );
+    }
+
+    #[test]
+    fn test_merge_stops_on_errors() {
+        let (tx, rx) = sync_channel(4);
+        tx.send(Ok(line(1, 1))).unwrap();
+        tx.send(Err(ProcessError::WriteError(std::io::Error::other("boom"))))
+            .unwrap();
+        drop(tx);
+        let mut emitted = 0;
+        let result = merge(vec![rx, input(&[2], 0)], |_, _| {
+            emitted += 1;
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert_eq!(emitted, 1);
+    }
+}
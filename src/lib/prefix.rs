@@ -0,0 +1,638 @@
+use std::{borrow::Cow, collections::VecDeque, io, sync::LazyLock};
+
+use jiff::Timestamp;
+use regex::Regex;
+use sonic_rs::{JsonValueTrait, LazyValue};
+
+use crate::{
+    fields::{FieldMap, value_text},
+    level::Level,
+    logline::LogLine,
+};
+
+// --------------------------------------------------------------------------
+
+// containerd (CRI), i.e. '2025-04-10T20:25:01.123Z stdout F {...}'. The 'P'
+// tag marks a line split by the runtime, continued on the next line.
+static CRI: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\d{4}-\d\d-\d\dT\S+) (stdout|stderr) ([PF]) ?(.*)$").unwrap());
+
+// docker compose, i.e. 'web-1  | {...}'
+static DOCKER_COMPOSE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([\w.-]+)\s*\| ?(.*)$").unwrap());
+
+// kubectl logs --prefix, i.e. '[pod/api-7d9f/api] {...}'
+static KUBECTL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\[([^\]]+)\] (.*)$").unwrap());
+
+// RFC 5424, i.e. '<34>1 2025-04-10T20:25:01Z host app 123 - - {...}', or the
+// older RFC 3164, i.e. '<34>Apr 10 20:25:01 host app[123]: {...}'
+static SYSLOG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r"^(?:<\d{1,3}>)?(?:",
+        r"1 \S+ (\S+ \S+) \S+ \S+ (?:-|(?:\[[^\]]*\])+) ",
+        r"|[A-Z][a-z]{2} [ \d]\d \d\d:\d\d:\d\d (\S+ [^\s:\[]+(?:\[\d+\])?): ",
+        r")(.*)$"
+    ))
+    .unwrap()
+});
+
+// The prefixes that are found by their pattern, in the order they are tried
+const PATTERN_PREFIXES: [LinePrefix; 4] = [
+    LinePrefix::Cri,
+    LinePrefix::Kubectl,
+    LinePrefix::DockerCompose,
+    LinePrefix::Syslog,
+];
+
+// The most lines of the other stream held back while joining a CRI partial
+// line, before giving up on it
+const MAX_HELD_LINES: usize = 1024;
+
+// --------------------------------------------------------------------------
+
+/// The kind of prefix written in front of the JSON of each line (or wrapper
+/// around it) by whatever collected the logs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LinePrefix {
+    /// Use the prefix most of the first lines of the input have, if any
+    #[default]
+    Auto,
+    /// The lines are JSON as they are
+    #[value(name = "none")]
+    Off,
+    /// containerd (CRI) logs, i.e. '2025-04-10T20:25:01Z stdout F {...}'
+    Cri,
+    /// The output of docker compose, i.e. 'web-1  | {...}'
+    DockerCompose,
+    /// The output of kubectl logs --prefix, i.e. '[pod/api/api] {...}'
+    Kubectl,
+    /// Syslog messages (RFC 5424 or RFC 3164)
+    Syslog,
+    /// The files of docker's json-file log driver, where each line of output
+    /// is wrapped as '{"log":"...","stream":"stdout","time":"..."}'
+    DockerJson,
+    /// The output of 'journalctl -o json', where the message of each entry
+    /// may itself be a JSON log line
+    Journald,
+}
+
+/// A line with its prefix taken off
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Stripped<'l> {
+    /// The rest of the line, which should be a JSON object
+    pub json: Cow<'l, str>,
+    /// What the prefix tells about the line, i.e. the stream, container or
+    /// host and app
+    pub context: Option<Cow<'l, str>>,
+    /// The time the prefix says the line was written at, if it says
+    pub time: Option<Cow<'l, str>>,
+    /// The level the prefix gives the line, for when it has none of its own
+    pub level: Option<Level>,
+    /// Whether the wrapper carries whole lines of output, so lines that are
+    /// not JSON are still messages (i.e. docker's json-file and journald)
+    pub plain_text: bool,
+}
+
+impl Stripped<'_> {
+    /// Where the JSON starts in `line`, the line it was stripped from, or 0
+    /// if it was unwrapped from it instead
+    pub(crate) fn json_offset(&self, line: &str) -> usize {
+        match &self.json {
+            Cow::Borrowed(json) => json.as_ptr() as usize - line.as_ptr() as usize,
+            Cow::Owned(_) => 0,
+        }
+    }
+
+    /// Parses the JSON of the line. Lines without a timestamp of their own get
+    /// that of the prefix, and if the wrapper carries plain text (and a time),
+    /// lines that are not JSON are taken as plain text messages. Anything else
+    /// that is not JSON is left to the invalid lines policy.
+    pub(crate) fn parse(&self, fields: &FieldMap) -> sonic_rs::Result<LogLine<'_>> {
+        match (LogLine::parse(&self.json, fields), self.time.as_deref()) {
+            (Ok(log_line), Some(time)) => Ok(log_line.with_fallback_timestamp(time)),
+            (Ok(log_line), None) => Ok(log_line),
+            (Err(_), Some(time)) if self.plain_text => Ok(LogLine::plain(time, &self.json)),
+            (Err(err), _) => Err(err),
+        }
+    }
+}
+
+impl LinePrefix {
+    /// Takes the prefix off `line`, if it has one. Lines without one are
+    /// returned as they are.
+    pub(crate) fn strip(self, line: &str) -> Stripped<'_> {
+        let unchanged = || Stripped {
+            json: Cow::Borrowed(line),
+            context: None,
+            time: None,
+            level: None,
+            plain_text: false,
+        };
+        match self {
+            LinePrefix::Off => unchanged(),
+            LinePrefix::Auto if line.starts_with(r#"{"log":"#) => {
+                unwrap_docker(line).unwrap_or_else(unchanged)
+            }
+            LinePrefix::Auto if line.starts_with(r#"{"__CURSOR":"#) => {
+                unwrap_journald(line).unwrap_or_else(unchanged)
+            }
+            LinePrefix::Auto if line.trim_start().starts_with('{') => unchanged(),
+            LinePrefix::DockerJson => unwrap_docker(line).unwrap_or_else(unchanged),
+            LinePrefix::Journald => unwrap_journald(line).unwrap_or_else(unchanged),
+            LinePrefix::Auto => PATTERN_PREFIXES
+                .into_iter()
+                .filter_map(|prefix| prefix.captures(line))
+                .find(|stripped| stripped.json.trim_start().starts_with('{'))
+                .unwrap_or_else(unchanged),
+            _ => self.captures(line).unwrap_or_else(unchanged),
+        }
+    }
+
+    /// Matches `line` against the pattern of a specific prefix
+    fn captures(self, line: &str) -> Option<Stripped<'_>> {
+        // the pattern, the groups the context may be in and the JSON group
+        let (rx, context, json): (&Regex, &[usize], usize) = match self {
+            LinePrefix::Auto | LinePrefix::Off | LinePrefix::DockerJson | LinePrefix::Journald => {
+                return None;
+            }
+            LinePrefix::Cri => (&CRI, &[2], 4),
+            LinePrefix::DockerCompose => (&DOCKER_COMPOSE, &[1], 2),
+            LinePrefix::Kubectl => (&KUBECTL, &[1], 2),
+            LinePrefix::Syslog => (&SYSLOG, &[1, 2], 3),
+        };
+        let caps = rx.captures(line)?;
+        Some(Stripped {
+            json: Cow::Borrowed(caps.get(json)?.as_str()),
+            context: context
+                .iter()
+                .find_map(|group| caps.get(*group))
+                .map(|context| Cow::Borrowed(context.as_str())),
+            // only CRI lines have a time of their own
+            time: caps
+                .get(1)
+                .filter(|_| self == LinePrefix::Cri)
+                .map(|time| Cow::Borrowed(time.as_str())),
+            level: None,
+            plain_text: false,
+        })
+    }
+
+    /// Decides on the prefix of the lines of an input from a sample of them,
+    /// as the one most of them have. Prefixes other than `Auto` are kept.
+    pub(crate) fn detect<'l>(self, lines: impl IntoIterator<Item = &'l str>) -> LinePrefix {
+        if self != LinePrefix::Auto {
+            return self;
+        }
+        let mut counts: Vec<(LinePrefix, usize)> = Vec::new();
+        for prefix in lines.into_iter().filter_map(prefix_of) {
+            match counts.iter_mut().find(|(seen, _)| *seen == prefix) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((prefix, 1)),
+            }
+        }
+        // on a tie, the prefix seen first wins
+        counts
+            .into_iter()
+            .rev()
+            .max_by_key(|(_, count)| *count)
+            .map_or(LinePrefix::Off, |(prefix, _)| prefix)
+    }
+
+    /// Joins the partial lines of CRI logs (split by the runtime when long)
+    /// with the lines that complete them, when the lines have CRI prefixes
+    pub(crate) fn join_partial_lines<I, P>(self, lines: I) -> PartialLines<I>
+    where
+        I: Iterator<Item = (P, io::Result<String>)>,
+    {
+        PartialLines {
+            lines,
+            held: VecDeque::new(),
+            enabled: self == LinePrefix::Cri,
+        }
+    }
+}
+
+/// The prefix a line has, where lines starting with JSON have none. Lines
+/// that do not tell (i.e. plain text) are left out.
+fn prefix_of(line: &str) -> Option<LinePrefix> {
+    if line.starts_with(r#"{"log":"#) && unwrap_docker(line).is_some() {
+        Some(LinePrefix::DockerJson)
+    } else if line.starts_with(r#"{"__CURSOR":"#) && unwrap_journald(line).is_some() {
+        Some(LinePrefix::Journald)
+    } else if line.trim_start().starts_with('{') {
+        Some(LinePrefix::Off)
+    } else {
+        PATTERN_PREFIXES.into_iter().find(|prefix| {
+            prefix
+                .captures(line)
+                .is_some_and(|stripped| stripped.json.trim_start().starts_with('{'))
+        })
+    }
+}
+
+/// Checks if a line starts with any of the known prefixes (that put the line in
+/// a pattern of their own), whatever comes after it
+pub(crate) fn has_known_prefix(line: &str) -> bool {
+    PATTERN_PREFIXES
+        .into_iter()
+        .any(|prefix| prefix.captures(line).is_some())
+}
+
+/// Takes the line written by the program out of the wrapper written around it
+/// by docker's json-file log driver, along with the stream and time
+fn unwrap_docker(line: &str) -> Option<Stripped<'_>> {
+    let field = |name| sonic_rs::get(line, [name]).ok();
+    let log: LazyValue = field("log")?;
+    if !log.is_str() {
+        return None;
+    }
+    let owned = |value: LazyValue| Cow::Owned(value_text(&value).into_owned());
+    Some(Stripped {
+        json: Cow::Owned(text_line(&log)),
+        context: field("stream").map(owned),
+        time: field("time").map(owned),
+        level: None,
+        plain_text: true,
+    })
+}
+
+/// Takes the message out of a journal entry, along with its time and priority.
+/// The host and unit it came from are kept as the context.
+fn unwrap_journald(line: &str) -> Option<Stripped<'_>> {
+    let field = |name| {
+        sonic_rs::get(line, [name])
+            .ok()
+            .map(|value: LazyValue| value_text(&value).into_owned())
+    };
+    let message = sonic_rs::get(line, ["MESSAGE"]).ok()?;
+    let micros = field("__REALTIME_TIMESTAMP")?.parse().ok()?;
+    let time = Timestamp::from_microsecond(micros).ok()?;
+
+    let host = field("_HOSTNAME");
+    let unit = field("_SYSTEMD_UNIT").or_else(|| field("SYSLOG_IDENTIFIER"));
+    let context = match (host, unit) {
+        (Some(host), Some(unit)) => Some(format!("{host} {unit}")),
+        (host, unit) => host.or(unit),
+    };
+    let level = field("PRIORITY")
+        .and_then(|priority| priority.parse().ok())
+        .map(Level::from_syslog);
+    Some(Stripped {
+        json: Cow::Owned(text_line(&message)),
+        context: context.map(Cow::Owned),
+        time: Some(Cow::Owned(time.to_string())),
+        level,
+        plain_text: true,
+    })
+}
+
+/// The text of a (string) value, without the line break at the end
+fn text_line(value: &LazyValue) -> String {
+    let mut text = value_text(value).into_owned();
+    text.truncate(text.trim_end_matches(['\r', '\n']).len());
+    text
+}
+
+/// The lines of an input, along with their positions, where CRI partial lines
+/// are joined into a single line (at the position of the first of them). Only
+/// lines of the same stream are joined, the lines of the other stream in
+/// between are held back and come after the joined line, in their order. A
+/// partial line that is not completed within `MAX_HELD_LINES` of them is
+/// passed on as far as it got.
+pub(crate) struct PartialLines<I: Iterator> {
+    lines: I,
+    held: VecDeque<I::Item>,
+    enabled: bool,
+}
+
+/// What a line is to a partial line being joined
+enum Continuation<'l> {
+    /// More of the line, and whether it is partial too
+    Part(&'l str, bool),
+    /// A CRI line of the other stream
+    OtherStream,
+    /// Anything else, which ends the partial line
+    End,
+}
+
+impl<'l> Continuation<'l> {
+    fn of(line: &'l io::Result<String>, stream: &str) -> Self {
+        let Some(caps) = line.as_ref().ok().and_then(|line| CRI.captures(line)) else {
+            return Continuation::End;
+        };
+        match (caps.get(2), caps.get(3), caps.get(4)) {
+            (Some(s), Some(tag), Some(content)) if s.as_str() == stream => {
+                Continuation::Part(content.as_str(), tag.as_str() == "P")
+            }
+            _ => Continuation::OtherStream,
+        }
+    }
+}
+
+impl<I, P> PartialLines<I>
+where
+    I: Iterator<Item = (P, io::Result<String>)>,
+{
+    /// Appends the rest of a partial line of `stream` to `joined`, from the
+    /// held lines first and then from the input
+    fn join(&mut self, stream: &str, joined: &mut String) {
+        let mut idx = 0;
+        while let Some((_, line)) = self.held.get(idx) {
+            match Continuation::of(line, stream) {
+                Continuation::Part(content, is_partial) => {
+                    joined.push_str(content);
+                    self.held.remove(idx);
+                    if !is_partial {
+                        return;
+                    }
+                }
+                Continuation::OtherStream => idx += 1,
+                Continuation::End => return,
+            }
+        }
+        for (pos, line) in self.lines.by_ref() {
+            match Continuation::of(&line, stream) {
+                Continuation::Part(content, is_partial) => {
+                    joined.push_str(content);
+                    if !is_partial {
+                        return;
+                    }
+                }
+                Continuation::OtherStream => {
+                    self.held.push_back((pos, line));
+                    if self.held.len() >= MAX_HELD_LINES {
+                        return;
+                    }
+                }
+                Continuation::End => {
+                    self.held.push_back((pos, line));
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl<I, P> Iterator for PartialLines<I>
+where
+    I: Iterator<Item = (P, io::Result<String>)>,
+{
+    type Item = (P, io::Result<String>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (pos, line) = self.held.pop_front().or_else(|| self.lines.next())?;
+        let line = match line {
+            Ok(line) if self.enabled => line,
+            line => return Some((pos, line)),
+        };
+        let Some(caps) = CRI.captures(&line).filter(|caps| &caps[3] == "P") else {
+            return Some((pos, Ok(line)));
+        };
+
+        let mut joined = format!("{} {} F {}", &caps[1], &caps[2], &caps[4]);
+        self.join(&caps[2], &mut joined);
+        Some((pos, Ok(joined)))
+    }
+}
+
+// --------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stripped<'l>(json: &'l str, context: &'l str) -> Stripped<'l> {
+        Stripped {
+            json: Cow::Borrowed(json),
+            context: Some(Cow::Borrowed(context)),
+            time: None,
+            level: None,
+            plain_text: false,
+        }
+    }
+
+    #[test]
+    fn test_strip_prefixes() {
+        let auto = LinePrefix::Auto;
+        let line = r#"2025-04-10T20:25:01.5Z stderr F {"level":"error"}"#;
+        assert_eq!(
+            auto.strip(line),
+            Stripped {
+                time: Some(Cow::Borrowed("2025-04-10T20:25:01.5Z")),
+                ..stripped(r#"{"level":"error"}"#, "stderr")
+            }
+        );
+        let line = r#"web-1  | {"level":"info"}"#;
+        assert_eq!(auto.strip(line), stripped(r#"{"level":"info"}"#, "web-1"));
+        let line = r#"[pod/api-7d9f/api] {"level":"info"}"#;
+        assert_eq!(
+            auto.strip(line),
+            stripped(r#"{"level":"info"}"#, "pod/api-7d9f/api")
+        );
+        let line = r#"<34>Apr 10 20:25:01 host app[123]: {"level":"info"}"#;
+        assert_eq!(
+            auto.strip(line),
+            stripped(r#"{"level":"info"}"#, "host app[123]")
+        );
+        let line = r#"<34>1 2025-04-10T20:25:01Z host app 123 ID47 - {"level":"info"}"#;
+        assert_eq!(
+            auto.strip(line),
+            stripped(r#"{"level":"info"}"#, "host app")
+        );
+
+        // lines starting with JSON, or without JSON after the prefix, are
+        // left alone
+        let line = r#"{"message":"web-1 | {}"}"#;
+        assert_eq!(auto.strip(line).json, line);
+        let line = "web-1  | Listening on port 80";
+        assert_eq!(auto.strip(line).json, line);
+
+        // unless we were told what to look for
+        let stripped = LinePrefix::DockerCompose.strip(line);
+        assert_eq!(stripped.json, "Listening on port 80");
+        assert_eq!(LinePrefix::Off.strip("web-1 | {}").json, "web-1 | {}");
+    }
+
+    #[test]
+    fn test_unwrap_docker_json() {
+        let fields = FieldMap::default();
+        let line = concat!(
+            r#"{"log":"{\"level\":\"warn\",\"message\":\"Slow\"}\n","#,
+            r#""stream":"stderr","time":"2025-04-10T20:25:01.123456789Z"}"#
+        );
+        for prefix in [LinePrefix::Auto, LinePrefix::DockerJson] {
+            let stripped = prefix.strip(line);
+            assert_eq!(stripped.json, r#"{"level":"warn","message":"Slow"}"#);
+            assert_eq!(stripped.context.as_deref(), Some("stderr"));
+            let log_line = stripped.parse(&fields).unwrap();
+            assert_eq!(log_line.message(), "Slow");
+            assert_eq!(log_line.timestamp(), "2025-04-10T20:25:01.123456789Z");
+        }
+        assert_eq!(LinePrefix::Off.strip(line).json, line);
+
+        // output that is not JSON is taken as plain text
+        let line =
+            r#"{"log":"Listening on port 80\r\n","stream":"stdout","time":"2025-04-10T20:25:02Z"}"#;
+        let stripped = LinePrefix::Auto.strip(line);
+        let log_line = stripped.parse(&fields).unwrap();
+        assert_eq!(log_line.message(), "Listening on port 80");
+        assert_eq!(log_line.timestamp(), "2025-04-10T20:25:02Z");
+        assert_eq!(log_line.level(), "");
+
+        // unlike the text after a CRI prefix, which is left to the invalid
+        // lines policy
+        let line = "2025-04-10T20:25:02Z stdout F Listening on port 80";
+        assert!(LinePrefix::Cri.strip(line).parse(&fields).is_err());
+    }
+
+    #[test]
+    fn test_unwrap_journald() {
+        let fields = FieldMap::default();
+        let entry = |message: &str| {
+            format!(
+                r#"{{"__CURSOR":"s=1","__REALTIME_TIMESTAMP":"1744316701133000","PRIORITY":"4","_HOSTNAME":"web01","_SYSTEMD_UNIT":"api.service","MESSAGE":{message}}}"#
+            )
+        };
+
+        let line = entry(r#""{\"message\":\"Slow\",\"level\":\"info\"}""#);
+        let stripped = LinePrefix::Auto.strip(&line);
+        assert_eq!(stripped.context.as_deref(), Some("web01 api.service"));
+        let log_line = stripped.parse(&fields).unwrap();
+        assert_eq!(log_line.message(), "Slow");
+        assert_eq!(log_line.level(), "info");
+        assert_eq!(log_line.timestamp(), "2025-04-10T20:25:01.133Z");
+        // the priority is there for messages without a level
+        assert_eq!(stripped.level, Some(Level::Warning));
+
+        let line = entry(r#""Disk almost full""#);
+        let stripped = LinePrefix::Journald.strip(&line);
+        let log_line = stripped.parse(&fields).unwrap();
+        assert_eq!(log_line.message(), "Disk almost full");
+        assert_eq!(log_line.level(), "");
+        assert_eq!(stripped.level, Some(Level::Warning));
+        assert_eq!(LinePrefix::Off.strip(&line).json, line);
+    }
+
+    #[test]
+    fn test_join_partial_lines() {
+        let lines = [
+            r#"2025-04-10T20:25:01Z stdout P {"message":"#,
+            r#"2025-04-10T20:25:01Z stdout P "a long "#,
+            r#"2025-04-10T20:25:01Z stdout F line"}"#,
+            r#"2025-04-10T20:25:02Z stdout F {}"#,
+            r#"2025-04-10T20:25:03Z stdout P {"message":"#,
+            "cut off",
+        ];
+        let numbered = || {
+            lines
+                .iter()
+                .enumerate()
+                .map(|(idx, line)| (idx + 1, Ok(line.to_string())))
+        };
+        let joined: Vec<_> = LinePrefix::Cri
+            .join_partial_lines(numbered())
+            .map(|(line_no, line)| (line_no, line.unwrap()))
+            .collect();
+        assert_eq!(
+            joined,
+            [
+                (
+                    1,
+                    r#"2025-04-10T20:25:01Z stdout F {"message":"a long line"}"#.to_string()
+                ),
+                (4, lines[3].to_string()),
+                (
+                    5,
+                    r#"2025-04-10T20:25:03Z stdout F {"message":"#.to_string()
+                ),
+                (6, lines[5].to_string()),
+            ]
+        );
+        assert_eq!(LinePrefix::Off.join_partial_lines(numbered()).count(), 6);
+    }
+
+    #[test]
+    fn test_detect_prefix() {
+        let detect = |lines: &[&str]| LinePrefix::Auto.detect(lines.iter().copied());
+        let cri = [
+            r#"2025-04-10T20:25:01Z stdout F {"level":"info"}"#,
+            "2025-04-10T20:25:02Z stdout F Listening on port 80",
+            r#"2025-04-10T20:25:03Z stderr P {"message":"#,
+        ];
+        assert_eq!(detect(&cri), LinePrefix::Cri);
+        let compose = [r#"web-1  | {"level":"info"}"#, r#"{"level":"info"}"#];
+        assert_eq!(detect(&compose), LinePrefix::DockerCompose);
+        let docker = [r#"{"log":"{}\n","stream":"stdout","time":"2025-04-10T20:25:01Z"}"#];
+        assert_eq!(detect(&docker), LinePrefix::DockerJson);
+
+        // most lines decide, and lines with nothing to tell have no say
+        let lines = [
+            r#"{"message":"web-1 | {}"}"#,
+            r#"web-1  | {"level":"info"}"#,
+            r#"{"level":"info"}"#,
+            "panic: runtime error",
+        ];
+        assert_eq!(detect(&lines), LinePrefix::Off);
+        assert_eq!(detect(&[]), LinePrefix::Off);
+        assert_eq!(LinePrefix::Syslog.detect(cri), LinePrefix::Syslog);
+    }
+
+    #[test]
+    fn test_join_partial_lines_of_interleaved_streams() {
+        let lines = [
+            r#"2025-04-10T20:25:01Z stdout P {"message":"#,
+            r#"2025-04-10T20:25:01Z stderr P {"message":"#,
+            r#"2025-04-10T20:25:01Z stdout F "out"}"#,
+            r#"2025-04-10T20:25:02Z stderr F "err"}"#,
+            r#"2025-04-10T20:25:02Z stderr F {}"#,
+            r#"2025-04-10T20:25:03Z stdout F {}"#,
+        ];
+        let joined: Vec<_> = LinePrefix::Cri
+            .join_partial_lines(
+                lines
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, line)| (idx + 1, Ok(line.to_string()))),
+            )
+            .map(|(line_no, line)| (line_no, line.unwrap()))
+            .collect();
+        assert_eq!(
+            joined,
+            [
+                (
+                    1,
+                    r#"2025-04-10T20:25:01Z stdout F {"message":"out"}"#.to_string()
+                ),
+                (
+                    2,
+                    r#"2025-04-10T20:25:01Z stderr F {"message":"err"}"#.to_string()
+                ),
+                (5, lines[4].to_string()),
+                (6, lines[5].to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_join_partial_lines_held_back_for_too_long() {
+        let other = r#"2025-04-10T20:25:01Z stderr F {}"#;
+        let late = r#"2025-04-10T20:25:02Z stdout F "late"}"#;
+        let lines = std::iter::once(r#"2025-04-10T20:25:01Z stdout P {"message":"#)
+            .chain(std::iter::repeat_n(other, MAX_HELD_LINES + 10))
+            .chain(std::iter::once(late));
+        let joined: Vec<_> = LinePrefix::Cri
+            .join_partial_lines(lines.map(|line| ((), Ok(line.to_string()))))
+            .map(|(_, line)| line.unwrap())
+            .collect();
+
+        // the partial line is given up on, and the lines held back follow it
+        assert_eq!(joined.len(), MAX_HELD_LINES + 12);
+        assert_eq!(joined[0], r#"2025-04-10T20:25:01Z stdout F {"message":"#);
+        assert!(
+            joined[1..=MAX_HELD_LINES + 10]
+                .iter()
+                .all(|line| line == other)
+        );
+        assert_eq!(joined[MAX_HELD_LINES + 11], late);
+    }
+}
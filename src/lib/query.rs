@@ -0,0 +1,466 @@
+use std::{cmp::Ordering, str::FromStr};
+
+use regex::Regex;
+use sonic_rs::{JsonValueTrait, LazyValue};
+
+use crate::fields::{FieldPath, value_text};
+
+// --------------------------------------------------------------------------
+
+/// An expression selecting log lines by their fields, i.e.
+/// `status >= 500 && path ~ "^/api"`.
+///
+/// - Fields are given by their (dotted) path, and a path on its own checks
+///   that the field is there and not `null`.
+/// - Fields are compared to numbers, strings, `true`, `false` and `null` with
+///   `==` (or `=`), `!=`, `<`, `<=`, `>` and `>=`. Comparing to a number
+///   compares numerically, also for strings holding numbers.
+/// - `~` and `!~` match a field against a regular expression.
+/// - Expressions are combined with `&&`, `||` and `!`, and grouped with
+///   parentheses.
+///
+/// Comparisons with a missing field are false, except for `!=` and `!~`.
+///
+#[derive(Debug, Clone)]
+pub struct Query {
+    expr: Expr,
+}
+
+impl Query {
+    /// Checks if the JSON object `json` is selected by the query
+    pub fn matches(&self, json: &str) -> bool {
+        self.expr.eval(json)
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser {
+            text: s,
+            tokens,
+            next: 0,
+        };
+        let expr = parser.parse_or()?;
+        match parser.advance() {
+            (Token::End, _) => Ok(Self { expr }),
+            (_, span) => Err(parser.error(span, "expected '&&', '||' or the end")),
+        }
+    }
+}
+
+/// An error in a query, pointing at where it went wrong
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    message: String,
+    text: String,
+    /// The position of the error, in characters
+    column: usize,
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at column {}\n  {}\n  {:>width$}",
+            self.message,
+            self.column + 1,
+            self.text,
+            "^",
+            width = self.column + 1
+        )
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+// --------------------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Exists(FieldPath),
+    Compare(FieldPath, CmpOp, Literal),
+    Matches(FieldPath, Regex),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Number(f64),
+    String(String),
+    Bool(bool),
+    Null,
+}
+
+impl Expr {
+    fn eval(&self, json: &str) -> bool {
+        match self {
+            Expr::Or(lhs, rhs) => lhs.eval(json) || rhs.eval(json),
+            Expr::And(lhs, rhs) => lhs.eval(json) && rhs.eval(json),
+            Expr::Not(expr) => !expr.eval(json),
+            Expr::Exists(path) => path.lookup(json).is_some_and(|value| !value.is_null()),
+            Expr::Compare(path, op, literal) => path
+                .lookup(json)
+                .is_some_and(|value| compare(&value, *op, literal)),
+            Expr::Matches(path, rx) => path
+                .lookup(json)
+                .is_some_and(|value| rx.is_match(&value_text(&value))),
+        }
+    }
+}
+
+/// Compares a JSON value to a literal. Values that cannot be compared to the
+/// literal (i.e. a string that is not a number, to a number) never match.
+fn compare(value: &LazyValue, op: CmpOp, literal: &Literal) -> bool {
+    let ordering = match literal {
+        Literal::Number(n) => {
+            let number = match value.as_f64() {
+                Some(number) => Some(number),
+                None if value.is_str() => value_text(value).trim().parse::<f64>().ok(),
+                None => None,
+            };
+            number.and_then(|number| number.partial_cmp(n))
+        }
+        Literal::String(s) if value.is_str() => Some(value_text(value).as_ref().cmp(s.as_str())),
+        Literal::String(s) => Some(value.as_raw_str().cmp(s.as_str())),
+        Literal::Bool(b) => (value.as_bool() == Some(*b)).then_some(Ordering::Equal),
+        Literal::Null => value.is_null().then_some(Ordering::Equal),
+    };
+    let Some(ordering) = ordering else {
+        return false;
+    };
+    match op {
+        CmpOp::Eq => ordering.is_eq(),
+        CmpOp::Ne => ordering.is_ne(),
+        CmpOp::Lt => ordering.is_lt(),
+        CmpOp::Le => ordering.is_le(),
+        CmpOp::Gt => ordering.is_gt(),
+        CmpOp::Ge => ordering.is_ge(),
+    }
+}
+
+// --------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// A field path, or a bare word used as a value
+    Word(String),
+    Number(f64),
+    String(String),
+    Cmp(CmpOp),
+    Match,
+    NotMatch,
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    End,
+}
+
+/// The byte range of a token in the query
+type Span = (usize, usize);
+
+fn is_word_start(c: char) -> bool {
+    c.is_alphabetic() || matches!(c, '_' | '@' | '$')
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '@' | '$' | '-' | '.')
+}
+
+/// Splits a query into tokens, ending with [`Token::End`]
+fn tokenize(text: &str) -> Result<Vec<(Token, Span)>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    let error = |pos: usize, message: &str| QueryError {
+        message: message.to_string(),
+        text: text.to_string(),
+        column: text[..pos].chars().count(),
+    };
+
+    while let Some((start, c)) = chars.next() {
+        let mut next_is = |expected: char| chars.next_if(|(_, c)| *c == expected).is_some();
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '&' if next_is('&') => Token::And,
+            '|' if next_is('|') => Token::Or,
+            '=' => {
+                next_is('=');
+                Token::Cmp(CmpOp::Eq)
+            }
+            '!' if next_is('=') => Token::Cmp(CmpOp::Ne),
+            '!' if next_is('~') => Token::NotMatch,
+            '!' => Token::Not,
+            '~' => Token::Match,
+            '<' if next_is('=') => Token::Cmp(CmpOp::Le),
+            '<' => Token::Cmp(CmpOp::Lt),
+            '>' if next_is('=') => Token::Cmp(CmpOp::Ge),
+            '>' => Token::Cmp(CmpOp::Gt),
+            '"' | '\'' => {
+                let quote = c;
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, c)) if c == quote => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, 'n')) => s.push('\n'),
+                            Some((_, 't')) => s.push('\t'),
+                            Some((_, c)) => s.push(c),
+                            None => return Err(error(start, "unterminated string")),
+                        },
+                        Some((_, c)) => s.push(c),
+                        None => return Err(error(start, "unterminated string")),
+                    }
+                }
+                Token::String(s)
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut end = start + c.len_utf8();
+                while let Some((pos, c)) = chars
+                    .next_if(|(_, c)| c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '-'))
+                {
+                    end = pos + c.len_utf8();
+                }
+                match text[start..end].parse::<f64>() {
+                    Ok(n) => Token::Number(n),
+                    Err(_) => return Err(error(start, "invalid number")),
+                }
+            }
+            c if is_word_start(c) => {
+                let mut end = start + c.len_utf8();
+                while let Some((pos, c)) = chars.next_if(|(_, c)| is_word_char(*c)) {
+                    end = pos + c.len_utf8();
+                }
+                Token::Word(text[start..end].to_string())
+            }
+            _ => return Err(error(start, &format!("unexpected '{c}'"))),
+        };
+        let end = chars.peek().map_or(text.len(), |(pos, _)| *pos);
+        tokens.push((token, (start, end)));
+    }
+    tokens.push((Token::End, (text.len(), text.len())));
+    Ok(tokens)
+}
+
+/// A recursive descent parser for queries, from the lowest precedence (`||`)
+/// to the highest (comparisons and parentheses)
+struct Parser<'t> {
+    text: &'t str,
+    tokens: Vec<(Token, Span)>,
+    next: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].0
+    }
+
+    fn advance(&mut self) -> (Token, Span) {
+        let token = self.tokens[self.next].clone();
+        // the end token stays put
+        self.next = (self.next + 1).min(self.tokens.len() - 1);
+        token
+    }
+
+    fn error(&self, (start, end): Span, message: &str) -> QueryError {
+        let message = match &self.text[start..end] {
+            "" => format!("{message}, found the end"),
+            found => format!("{message}, found '{found}'"),
+        };
+        QueryError {
+            message,
+            text: self.text.to_string(),
+            column: self.text[..start].chars().count(),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, QueryError> {
+        let mut lhs = self.parse_and()?;
+        while *self.peek() == Token::Or {
+            self.advance();
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.parse_and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, QueryError> {
+        let mut lhs = self.parse_unary()?;
+        while *self.peek() == Token::And {
+            self.advance();
+            lhs = Expr::And(Box::new(lhs), Box::new(self.parse_unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, QueryError> {
+        if *self.peek() == Token::Not {
+            self.advance();
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, QueryError> {
+        match self.advance() {
+            (Token::LParen, _) => {
+                let expr = self.parse_or()?;
+                match self.advance() {
+                    (Token::RParen, _) => Ok(expr),
+                    (_, span) => Err(self.error(span, "expected ')'")),
+                }
+            }
+            (Token::Word(path), span) => {
+                let path = path
+                    .parse::<FieldPath>()
+                    .map_err(|err| self.error(span, &err.to_string()))?;
+                self.parse_condition(path)
+            }
+            (_, span) => Err(self.error(span, "expected a field path, '(' or '!'")),
+        }
+    }
+
+    /// Parses what follows a field path, if anything
+    fn parse_condition(&mut self, path: FieldPath) -> Result<Expr, QueryError> {
+        match self.peek().clone() {
+            Token::Cmp(op) => {
+                let (_, op_span) = self.advance();
+                let literal = self.parse_literal()?;
+                let is_ordering = !matches!(op, CmpOp::Eq | CmpOp::Ne);
+                if is_ordering && matches!(literal, Literal::Bool(_) | Literal::Null) {
+                    return Err(self.error(op_span, "expected '==' or '!=' for this value"));
+                }
+                Ok(match op {
+                    CmpOp::Ne => Expr::Not(Box::new(Expr::Compare(path, CmpOp::Eq, literal))),
+                    op => Expr::Compare(path, op, literal),
+                })
+            }
+            token @ (Token::Match | Token::NotMatch) => {
+                self.advance();
+                let rx = match self.advance() {
+                    (Token::String(rx) | Token::Word(rx), span) => Regex::new(&rx)
+                        .map_err(|err| self.error(span, &format!("invalid regex ({err})")))?,
+                    (_, span) => return Err(self.error(span, "expected a regex")),
+                };
+                let expr = Expr::Matches(path, rx);
+                Ok(match token {
+                    Token::NotMatch => Expr::Not(Box::new(expr)),
+                    _ => expr,
+                })
+            }
+            _ => Ok(Expr::Exists(path)),
+        }
+    }
+
+    fn parse_literal(&mut self) -> Result<Literal, QueryError> {
+        match self.advance() {
+            (Token::Number(n), _) => Ok(Literal::Number(n)),
+            (Token::String(s), _) => Ok(Literal::String(s)),
+            (Token::Word(word), _) => Ok(match word.as_str() {
+                "true" => Literal::Bool(true),
+                "false" => Literal::Bool(false),
+                "null" => Literal::Null,
+                _ => Literal::String(word),
+            }),
+            (_, span) => Err(self.error(span, "expected a value")),
+        }
+    }
+}
+
+// --------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = r#"{
+        "status": 503, "path": "/api/users", "user": {"id": "42", "admin": false},
+        "note": null, "message": "say \"hi\"", "log.level": "warn"
+    }"#;
+
+    fn matches(query: &str) -> bool {
+        query.parse::<Query>().unwrap().matches(LINE)
+    }
+
+    fn error(query: &str) -> String {
+        query.parse::<Query>().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_query_comparisons() {
+        assert!(matches("status >= 500"));
+        assert!(matches("status == 503 && status != 504"));
+        assert!(!matches("status < 500"));
+        assert!(matches("status > 5e2"));
+        // strings holding numbers compare as numbers
+        assert!(matches("user.id > 7"));
+        assert!(!matches("user.id > '7'"));
+        assert!(matches("path == '/api/users'"));
+        assert!(matches("path > \"/api\""));
+        assert!(matches("user.admin == false && note == null"));
+        assert!(matches(r#"message = 'say "hi"'"#));
+        assert!(matches("log.level == warn"));
+        assert!(!matches("status == '503x'"));
+    }
+
+    #[test]
+    fn test_query_regex_and_existence() {
+        assert!(matches(r#"path ~ "^/api""#));
+        assert!(matches(r#"path !~ "^/admin""#));
+        assert!(matches("message ~ hi"));
+        assert!(matches("user.id"));
+        assert!(!matches("note"));
+        assert!(!matches("missing"));
+        // comparisons with missing fields are false, unless negated
+        assert!(!matches("missing == 1") && !matches("missing < 1"));
+        assert!(matches("missing != 1") && matches("missing !~ x"));
+    }
+
+    #[test]
+    fn test_query_boolean_operators() {
+        assert!(matches("status >= 500 && path ~ \"/api\""));
+        assert!(matches("status < 500 || user.admin == false"));
+        assert!(!matches("!(status >= 500)"));
+        assert!(matches("!missing && !!status"));
+        // && binds tighter than ||
+        assert!(matches("status == 1 && missing || status == 503"));
+        assert!(!matches("status == 1 && (missing || status == 503)"));
+    }
+
+    #[test]
+    fn test_query_errors() {
+        assert_eq!(
+            error("status >= && x"),
+            "expected a value, found '&&' at column 11\n  status >= && x\n            ^"
+        );
+        assert_eq!(
+            error("(status > 1"),
+            "expected ')', found the end at column 12\n  (status > 1\n             ^"
+        );
+        assert_eq!(
+            error("a == 1 b"),
+            "expected '&&', '||' or the end, found 'b' at column 8\n  a == 1 b\n         ^"
+        );
+        assert!(error("path ~ '('").starts_with("invalid regex"));
+        assert!(error("note > null").starts_with("expected '==' or '!=' for this value"));
+        assert!(error("a == 'open").starts_with("unterminated string at column 6"));
+        assert!(error("a # 1").starts_with("unexpected '#' at column 3"));
+        assert!(error("a..b").starts_with("field path 'a..b' has an empty key"));
+    }
+}
@@ -0,0 +1,328 @@
+use sonic_rs::{JsonValueTrait, LazyValue};
+
+use crate::{
+    fields::{FieldMap, FieldPath, paths},
+    level::Level,
+    logline::is_json_object,
+    timestamp::TimestampEncoding,
+};
+
+// --------------------------------------------------------------------------
+
+/// The JSON loggers we have built-in schemas for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Winston,
+    Pino,
+    Bunyan,
+    Zap,
+    Logrus,
+    Slog,
+    Clef,
+    Ecs,
+    Gelf,
+    Journald,
+}
+
+impl Format {
+    /// All the formats, in the order they are listed in help texts
+    pub const ALL: [Format; 10] = [
+        Format::Winston,
+        Format::Pino,
+        Format::Bunyan,
+        Format::Zap,
+        Format::Logrus,
+        Format::Slog,
+        Format::Clef,
+        Format::Ecs,
+        Format::Gelf,
+        Format::Journald,
+    ];
+
+    /// The name of the format, as used on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Winston => "winston",
+            Format::Pino => "pino",
+            Format::Bunyan => "bunyan",
+            Format::Zap => "zap",
+            Format::Logrus => "logrus",
+            Format::Slog => "slog",
+            Format::Clef => "clef",
+            Format::Ecs => "ecs",
+            Format::Gelf => "gelf",
+            Format::Journald => "journald",
+        }
+    }
+
+    /// The schema used by this format
+    pub fn schema(self) -> Schema {
+        use LevelEncoding::*;
+        use TimestampEncoding::*;
+
+        let (timestamp, level, message) = match self {
+            Format::Winston => (&["timestamp"][..], &["level"][..], &["message"][..]),
+            Format::Pino | Format::Bunyan => (&["time"][..], &["level"][..], &["msg"][..]),
+            Format::Zap => (&["ts"][..], &["level"][..], &["msg"][..]),
+            Format::Logrus | Format::Slog => (&["time"][..], &["level"][..], &["msg"][..]),
+            Format::Clef => (&["@t"][..], &["@l"][..], &["@m", "@mt"][..]),
+            Format::Ecs => (&["@timestamp"][..], &["log.level"][..], &["message"][..]),
+            Format::Gelf => (
+                &["timestamp"][..],
+                &["level"][..],
+                &["short_message", "full_message"][..],
+            ),
+            Format::Journald => (
+                &["__REALTIME_TIMESTAMP"][..],
+                &["PRIORITY"][..],
+                &["MESSAGE"][..],
+            ),
+        };
+        let stack = match self {
+            Format::Winston | Format::Logrus | Format::Slog => &["stack"][..],
+            Format::Pino | Format::Bunyan => &["err.stack", "stack"][..],
+            Format::Zap => &["stacktrace"][..],
+            Format::Clef => &["@x"][..],
+            Format::Ecs => &["error.stack_trace"][..],
+            Format::Gelf => &["_stack"][..],
+            Format::Journald => &[][..],
+        };
+        let (levels, timestamps) = match self {
+            Format::Pino => (Numeric, EpochMillis),
+            Format::Bunyan => (Numeric, Iso8601),
+            Format::Zap => (Names, EpochSeconds),
+            Format::Gelf => (Syslog, EpochSeconds),
+            Format::Journald => (Syslog, EpochMicros),
+            _ => (Names, Iso8601),
+        };
+        // serilog leaves out the level when it is 'Information'
+        let default_level = match self {
+            Format::Clef => Level::Info,
+            _ => Level::Unknown,
+        };
+
+        Schema {
+            fields: FieldMap::new(paths(timestamp), paths(level), paths(message))
+                .with_stack(paths(stack)),
+            levels,
+            default_level,
+            timestamps,
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// --------------------------------------------------------------------------
+
+/// How the levels of a log are encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelEncoding {
+    /// Level names only, i.e. 'info' or 'WARN'
+    Names,
+    /// pino/bunyan numeric levels (10 - 60), or names
+    Numeric,
+    /// syslog severities (0 - 7), or names
+    Syslog,
+}
+
+/// Describes where to find things in the log lines of a particular logger and
+/// how to interpret them
+#[derive(Debug, Clone)]
+pub struct Schema {
+    fields: FieldMap,
+    levels: LevelEncoding,
+    default_level: Level,
+    timestamps: TimestampEncoding,
+}
+
+impl Schema {
+    /// Overrides the paths of the schema with the lists of paths that are not
+    /// empty in `fields`
+    pub fn with_fields(mut self, fields: FieldMap) -> Self {
+        self.fields = fields.overlay(&self.fields);
+        self
+    }
+
+    pub fn fields(&self) -> &FieldMap {
+        &self.fields
+    }
+    pub fn timestamps(&self) -> TimestampEncoding {
+        self.timestamps
+    }
+
+    /// Translates the (raw) level value of a line to a [`Level`]
+    pub fn level(&self, level: &str) -> Level {
+        if level.is_empty() {
+            return self.default_level;
+        }
+        let number = match self.levels {
+            LevelEncoding::Names => None,
+            LevelEncoding::Numeric | LevelEncoding::Syslog => level.parse::<u64>().ok(),
+        };
+        match (number, self.levels) {
+            (Some(n), LevelEncoding::Numeric) => Level::from_pino(n),
+            (Some(n), _) => Level::from_syslog(n),
+            (None, _) => Level::from_name(level),
+        }
+    }
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        Format::Winston.schema()
+    }
+}
+
+// --------------------------------------------------------------------------
+
+/// Picks the format that best matches the given sample of log lines, or
+/// `None` if no format matches any of them. Lines that are not JSON objects
+/// are ignored.
+///
+/// Each line is scored against each format by which of the format's fields
+/// are present and whether their values look right, i.e. pino has numeric
+/// timestamps and levels where bunyan has ISO 8601 timestamps.
+///
+pub fn detect_format<S: AsRef<str>>(lines: &[S]) -> Option<Format> {
+    let lines = lines
+        .iter()
+        .map(AsRef::as_ref)
+        .filter(|line| is_json_object(line))
+        .collect::<Vec<_>>();
+
+    let mut best: Option<(Format, u32)> = None;
+    for format in Format::ALL {
+        let schema = format.schema();
+        let score = lines
+            .iter()
+            .map(|line| score_line(format, &schema, line))
+            .sum();
+        // on ties, the first format listed wins
+        if score > 0 && best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((format, score));
+        }
+    }
+    best.map(|(format, _)| format)
+}
+
+/// How well a single line matches a format
+fn score_line(format: Format, schema: &Schema, line: &str) -> u32 {
+    let lookup = |paths: &[FieldPath]| paths.iter().find_map(|path| path.lookup(line));
+    let mut score = 0;
+
+    // journald writes its numbers as strings
+    let is_number = |value: &LazyValue| {
+        value.is_number()
+            || value
+                .as_str()
+                .is_some_and(|s| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()))
+    };
+
+    if let Some(ts) = lookup(schema.fields.timestamp()) {
+        let numeric = schema.timestamps != TimestampEncoding::Iso8601;
+        score += if is_number(&ts) == numeric { 3 } else { 1 };
+    }
+    if lookup(schema.fields.message()).is_some() {
+        score += 3;
+    }
+    if let Some(level) = lookup(schema.fields.level()) {
+        score += 1;
+        let numeric = schema.levels != LevelEncoding::Names;
+        if is_number(&level) == numeric {
+            score += 1;
+        }
+        // logrus and slog share field names, but not the case of the levels
+        let name = level.as_str().unwrap_or_default();
+        score += match format {
+            Format::Logrus => u32::from(name.bytes().any(|b| b.is_ascii_lowercase())),
+            Format::Slog => u32::from(name.bytes().any(|b| b.is_ascii_uppercase())),
+            _ => 0,
+        };
+    }
+    score
+}
+
+// --------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_by_format() {
+        assert_eq!(Format::Winston.schema().level("warn"), Level::Warning);
+        assert_eq!(Format::Winston.schema().level("40"), Level::Unknown);
+        assert_eq!(Format::Pino.schema().level("40"), Level::Warning);
+        assert_eq!(Format::Bunyan.schema().level("info"), Level::Info);
+        assert_eq!(Format::Gelf.schema().level("3"), Level::Error);
+        assert_eq!(Format::Journald.schema().level("4"), Level::Warning);
+        assert_eq!(Format::Slog.schema().level("DEBUG"), Level::Debug);
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let detect = |line: &str| detect_format(&[line]);
+        assert_eq!(
+            detect(r#"{"timestamp":"2025-01-01T12:00:00Z","level":"info","message":"hi"}"#),
+            Some(Format::Winston)
+        );
+        assert_eq!(
+            detect(r#"{"level":30,"time":1744316701133,"pid":1,"msg":"hi"}"#),
+            Some(Format::Pino)
+        );
+        assert_eq!(
+            detect(r#"{"v":0,"level":30,"time":"2025-01-01T12:00:00Z","msg":"hi"}"#),
+            Some(Format::Bunyan)
+        );
+        assert_eq!(
+            detect(r#"{"level":"info","ts":1744316701.133,"msg":"hi"}"#),
+            Some(Format::Zap)
+        );
+        assert_eq!(
+            detect(r#"{"level":"warning","time":"2025-01-01T12:00:00Z","msg":"hi"}"#),
+            Some(Format::Logrus)
+        );
+        assert_eq!(
+            detect(r#"{"time":"2025-01-01T12:00:00Z","level":"WARN","msg":"hi"}"#),
+            Some(Format::Slog)
+        );
+        assert_eq!(
+            detect(r#"{"@t":"2025-01-01T12:00:00Z","@mt":"hi {Name}","Name":"x"}"#),
+            Some(Format::Clef)
+        );
+        assert_eq!(
+            detect(r#"{"@timestamp":"2025-01-01T12:00:00Z","log.level":"info","message":"hi"}"#),
+            Some(Format::Ecs)
+        );
+        assert_eq!(
+            detect(r#"{"version":"1.1","timestamp":1744316701.1,"level":6,"short_message":"hi"}"#),
+            Some(Format::Gelf)
+        );
+        assert_eq!(
+            detect(concat!(
+                r#"{"__CURSOR":"s=1","__REALTIME_TIMESTAMP":"1744316701133000","#,
+                r#""PRIORITY":"6","_SYSTEMD_UNIT":"api.service","MESSAGE":"hi"}"#
+            )),
+            Some(Format::Journald)
+        );
+    }
+
+    #[test]
+    fn test_detect_format_without_matches() {
+        assert_eq!(detect_format(&["not json", "[1,2]"]), None);
+        assert_eq!(detect_format(&[r#"{"a":1}"#]), None);
+        assert_eq!(detect_format::<&str>(&[]), None);
+    }
+
+    #[test]
+    fn test_default_level() {
+        assert_eq!(Format::Clef.schema().level(""), Level::Info);
+        assert_eq!(Format::Clef.schema().level("Warning"), Level::Warning);
+        assert_eq!(Format::Winston.schema().level(""), Level::Unknown);
+    }
+}
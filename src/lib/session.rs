@@ -7,7 +7,7 @@ pub enum SessionStartDetector {
     /// no new sessions are detected
     Never,
     /// If a message is exactly equal to this, it's a new session
-    Equals(&'static str),
+    Equals(String),
     /// If a message matches this regular expression, it's a new session
     Matches(Regex),
 }
@@ -20,8 +20,8 @@ impl SessionStartDetector {
 
     /// Creates a new session detector that detects a new session if the message
     /// is equal to a given string
-    pub fn from_message(s: &'static str) -> Self {
-        Self::Equals(s)
+    pub fn from_message(s: impl Into<String>) -> Self {
+        Self::Equals(s.into())
     }
 
     /// Creates a new session detector that detects a new session if the message
@@ -34,7 +34,7 @@ impl SessionStartDetector {
     pub fn is_new_session(&self, line: &LogLine) -> bool {
         match self {
             SessionStartDetector::Never => false,
-            SessionStartDetector::Equals(s) => line.message() == *s,
+            SessionStartDetector::Equals(s) => line.message() == s,
             SessionStartDetector::Matches(rx) => rx.is_match(line.message()),
         }
     }
@@ -77,6 +77,14 @@ mod tests {
         assert!(!detector.is_new_session(&LogLine::from_message("not empty")));
     }
 
+    #[test]
+    fn test_equals_from_owned_string() {
+        let message = String::from("service started");
+        let detector = SessionStartDetector::from_message(message);
+        assert!(detector.is_new_session(&LogLine::from_message("service started")));
+        assert!(!detector.is_new_session(&LogLine::from_message("service")));
+    }
+
     #[test]
     fn test_equals_case_sensitive() {
         let detector = SessionStartDetector::from_message("Start");
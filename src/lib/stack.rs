@@ -0,0 +1,55 @@
+/// Checks if a line of a message or stack trace is a stack frame, i.e.
+/// '    at foo (/app/index.js:10:5)' (JavaScript, Java, .NET),
+/// '  File "app.py", line 10, in foo' (Python) or an indented source location
+/// (Go, Rust).
+pub(crate) fn is_frame(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    if trimmed.len() == line.len() || trimmed.is_empty() {
+        return false;
+    }
+    trimmed.starts_with("at ") || trimmed.starts_with("File \"") || trimmed.starts_with('/')
+}
+
+/// Checks if a stack frame is from library code (i.e. not the application)
+pub(crate) fn is_library_frame(frame: &str) -> bool {
+    const LIBRARY_MARKERS: [&str; 8] = [
+        "node_modules/",
+        "node_modules\\",
+        "node:internal",
+        "(internal/",
+        "site-packages/",
+        "dist-packages/",
+        "/go/pkg/mod/",
+        "/.cargo/registry/",
+    ];
+    LIBRARY_MARKERS.iter().any(|marker| frame.contains(marker))
+}
+
+// --------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_frame() {
+        assert!(is_frame("    at foo (/app/index.js:10:5)"));
+        assert!(is_frame("\tat com.example.Foo.bar(Foo.java:10)"));
+        assert!(is_frame(r#"  File "app.py", line 10, in foo"#));
+        assert!(is_frame("\t/app/main.go:42 +0x1d"));
+        assert!(!is_frame("Error: boom"));
+        assert!(!is_frame("at the start of the line"));
+        assert!(!is_frame("   "));
+    }
+
+    #[test]
+    fn test_is_library_frame() {
+        assert!(is_library_frame(
+            "    at Layer.handle (/app/node_modules/express/lib/router/layer.js:95:5)"
+        ));
+        assert!(is_library_frame(
+            "    at process.processTicksAndRejections (node:internal/process/task_queues:95:5)"
+        ));
+        assert!(!is_library_frame("    at foo (/app/index.js:10:5)"));
+    }
+}
@@ -0,0 +1,9 @@
+//! Helpers shared by the tests of several modules
+
+/// A tiny xorshift PRNG, so the fuzz tests are repeatable
+pub(crate) fn xorshift(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
@@ -0,0 +1,425 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    str::FromStr,
+};
+
+use jiff::{SignedDuration, Timestamp, civil, tz::TimeZone};
+
+use crate::timestamp::parse_iso;
+
+/// Below this many bytes, the seek stops halving the file and leaves the rest
+/// to be read line by line
+const LINEAR_SCAN_SIZE: u64 = 64 * 1024;
+
+// --------------------------------------------------------------------------
+
+/// One end of a time range, as given on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeBound {
+    /// A point in time, i.e. '2025-01-01T14:02:00Z'
+    At(Timestamp),
+    /// A time of day on the date of the first line, i.e. '14:02'
+    TimeOfDay(civil::Time),
+    /// A duration before now, i.e. '15m'
+    Ago(SignedDuration),
+}
+
+impl FromStr for TimeBound {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // a time of day like '14:02:03' also passes for a duration
+        if let Some(ts) = parse_iso(s) {
+            Ok(TimeBound::At(ts))
+        } else if let Ok(time) = s.parse::<civil::Time>() {
+            Ok(TimeBound::TimeOfDay(time))
+        } else if let Ok(ago) = s.parse::<SignedDuration>() {
+            Ok(TimeBound::Ago(ago.abs()))
+        } else {
+            Err(format!(
+                "'{s}' is not a timestamp, a time of day or a duration \
+                 (i.e. '2025-01-01T14:02:00Z', '14:02' or '15m')"
+            ))
+        }
+    }
+}
+
+impl TimeBound {
+    /// The point in time of the bound, where a time of day is taken on the
+    /// date of `first` in `zone`
+    fn resolve(self, first: Timestamp, zone: &TimeZone) -> Timestamp {
+        match self {
+            TimeBound::At(ts) => ts,
+            TimeBound::TimeOfDay(time) => first
+                .to_zoned(zone.clone())
+                .date()
+                .to_datetime(time)
+                .to_zoned(zone.clone())
+                .map(|zoned| zoned.timestamp())
+                .unwrap_or(first),
+            TimeBound::Ago(ago) => Timestamp::now().checked_sub(ago).unwrap_or(Timestamp::MIN),
+        }
+    }
+}
+
+/// The range of time to show lines from. The start is included, the end is
+/// not, so consecutive ranges do not overlap.
+#[derive(Debug, Clone, Default)]
+pub struct TimeRange {
+    since: Option<TimeBound>,
+    until: Option<TimeBound>,
+}
+
+impl TimeRange {
+    /// Show only lines at or after `since`
+    pub fn with_since(mut self, since: TimeBound) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Show only lines before `until`
+    pub fn with_until(mut self, until: TimeBound) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    /// Returns `true` if the range has no ends, and so lets everything through
+    pub fn is_unbounded(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    /// Works out the points in time of the ends of the range, given the
+    /// timestamp of the first line
+    pub(crate) fn resolve(&self, first: Timestamp, zone: &TimeZone) -> Bounds {
+        Bounds {
+            since: self.since.map(|since| since.resolve(first, zone)),
+            until: self.until.map(|until| until.resolve(first, zone)),
+        }
+    }
+}
+
+/// A [`TimeRange`] resolved to points in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Bounds {
+    pub since: Option<Timestamp>,
+    pub until: Option<Timestamp>,
+}
+
+impl Bounds {
+    pub fn contains(&self, ts: Timestamp) -> bool {
+        self.since.is_none_or(|since| ts >= since) && self.until.is_none_or(|until| ts < until)
+    }
+}
+
+// --------------------------------------------------------------------------
+
+/// Reads up to `count` lines from the start of `file`, replacing invalid UTF-8
+/// (i.e. in compressed files) instead of failing
+pub(crate) fn head_lines(file: &mut File, count: usize) -> io::Result<Vec<String>> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut reader = BufReader::new(file);
+    let mut lines = Vec::with_capacity(count);
+    let mut line = Vec::new();
+    while lines.len() < count {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        lines.push(String::from_utf8_lossy(trim_eol(&line)).into_owned());
+    }
+    Ok(lines)
+}
+
+/// Finds the timestamp of the last line of `file` that has one, looking only
+/// at the end of the file
+pub(crate) fn last_timestamp<F>(file: &mut File, timestamp_of: F) -> io::Result<Option<Timestamp>>
+where
+    F: Fn(&str) -> Option<Timestamp>,
+{
+    let len = file.seek(SeekFrom::End(0))?;
+    let start = len.saturating_sub(LINEAR_SCAN_SIZE);
+    file.seek(SeekFrom::Start(start))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+
+    let mut lines = tail.split(|byte| *byte == b'\n');
+    if start > 0 {
+        // we most likely started in the middle of a line
+        lines.next();
+    }
+    Ok(lines
+        .rev()
+        .find_map(|line| timestamp_of(&String::from_utf8_lossy(trim_eol(line)))))
+}
+
+/// Finds the offset of a line starting shortly before the first line at or
+/// after `since` in a file sorted by time, by binary search. Lines without a
+/// timestamp are passed over.
+pub(crate) fn seek_offset<F>(file: &mut File, since: Timestamp, timestamp_of: F) -> io::Result<u64>
+where
+    F: Fn(&str) -> Option<Timestamp>,
+{
+    let len = file.seek(SeekFrom::End(0))?;
+    // the lines starting before 'low' are all before 'since', and 'low' is
+    // always at the start of a line
+    let (mut low, mut high) = (0, len);
+    while high - low > LINEAR_SCAN_SIZE {
+        let mid = low + (high - low) / 2;
+        match next_timestamp(file, mid, high, &timestamp_of)? {
+            Some((start, ts)) if ts < since => low = start,
+            _ => high = mid,
+        }
+    }
+    Ok(low)
+}
+
+/// Finds the first line with a timestamp starting after `from` and before
+/// `until`, and returns where it starts along with its timestamp
+fn next_timestamp<F>(
+    file: &mut File,
+    from: u64,
+    until: u64,
+    timestamp_of: &F,
+) -> io::Result<Option<(u64, Timestamp)>>
+where
+    F: Fn(&str) -> Option<Timestamp>,
+{
+    file.seek(SeekFrom::Start(from))?;
+    let mut reader = BufReader::new(file);
+    let mut line = Vec::new();
+
+    // skip the rest of the line we landed in
+    let mut start = from + reader.read_until(b'\n', &mut line)? as u64;
+    while start < until {
+        line.clear();
+        let n = reader.read_until(b'\n', &mut line)?;
+        if n == 0 {
+            break;
+        }
+        if let Some(ts) = timestamp_of(&String::from_utf8_lossy(trim_eol(&line))) {
+            return Ok(Some((start, ts)));
+        }
+        start += n as u64;
+    }
+    Ok(None)
+}
+
+/// Finds the last line of `file` before `offset` that `is_match` accepts, by
+/// reading the file backwards from `offset`, which is at the start of a line.
+/// Returns where the line starts along with the line.
+pub(crate) fn last_line_before<F>(
+    file: &mut File,
+    offset: u64,
+    is_match: F,
+) -> io::Result<Option<(u64, String)>>
+where
+    F: Fn(&str) -> bool,
+{
+    let mut end = offset;
+    // the start of the line that began before the chunk read last
+    let mut rest = Vec::new();
+    while end > 0 {
+        let start = end.saturating_sub(LINEAR_SCAN_SIZE);
+        file.seek(SeekFrom::Start(start))?;
+        let mut chunk = vec![0; (end - start) as usize];
+        file.read_exact(&mut chunk)?;
+        chunk.append(&mut rest);
+
+        // the first line of the chunk most likely started before it
+        let first = match start {
+            0 => 0,
+            _ => chunk
+                .iter()
+                .position(|byte| *byte == b'\n')
+                .map_or(chunk.len(), |idx| idx + 1),
+        };
+        let mut line_end = chunk.len();
+        while line_end > first {
+            let line_start = chunk[first..line_end - 1]
+                .iter()
+                .rposition(|byte| *byte == b'\n')
+                .map_or(first, |idx| first + idx + 1);
+            let line = String::from_utf8_lossy(trim_eol(&chunk[line_start..line_end]));
+            if is_match(&line) {
+                return Ok(Some((start + line_start as u64, line.into_owned())));
+            }
+            line_end = line_start;
+        }
+        chunk.truncate(first);
+        rest = chunk;
+        end = start;
+    }
+    Ok(None)
+}
+
+/// Counts the lines of `file` before `offset`, by reading up to it
+pub(crate) fn count_lines(file: &mut File, offset: u64) -> io::Result<usize> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut skipped = file.take(offset);
+    let mut buf = vec![0; 64 * 1024];
+    let mut lines = 0;
+    loop {
+        match skipped.read(&mut buf) {
+            Ok(0) => return Ok(lines),
+            Ok(n) => lines += buf[..n].iter().filter(|b| **b == b'\n').count(),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn trim_eol(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\n")
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .unwrap_or(line)
+}
+
+// --------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn ts(raw: &str) -> Timestamp {
+        raw.parse().unwrap()
+    }
+
+    /// Reads the timestamp from a line like '<seconds> some text'
+    fn seconds_of(line: &str) -> Option<Timestamp> {
+        let seconds = line.split_once(' ')?.0.parse().ok()?;
+        Timestamp::from_second(seconds).ok()
+    }
+
+    #[test]
+    fn test_parse_time_bound() {
+        assert_eq!(
+            "15m".parse(),
+            Ok(TimeBound::Ago(SignedDuration::from_mins(15)))
+        );
+        assert_eq!(
+            "14:02".parse(),
+            Ok(TimeBound::TimeOfDay(civil::time(14, 2, 0, 0)))
+        );
+        assert_eq!(
+            "14:02:03".parse(),
+            Ok(TimeBound::TimeOfDay(civil::time(14, 2, 3, 0)))
+        );
+        assert_eq!(
+            "2025-01-01T14:02:00Z".parse(),
+            Ok(TimeBound::At(ts("2025-01-01T14:02:00Z")))
+        );
+        assert_eq!(
+            "2025-01-01 14:02".parse(),
+            Ok(TimeBound::At(ts("2025-01-01T14:02:00Z")))
+        );
+        assert!("yesterday".parse::<TimeBound>().is_err());
+    }
+
+    #[test]
+    fn test_resolve_time_range() {
+        let range = TimeRange::default()
+            .with_since("14:02".parse().unwrap())
+            .with_until("2025-01-02T00:00:00Z".parse().unwrap());
+        let bounds = range.resolve(ts("2025-01-01T09:30:00Z"), &TimeZone::UTC);
+        assert_eq!(bounds.since, Some(ts("2025-01-01T14:02:00Z")));
+        assert!(!bounds.contains(ts("2025-01-01T14:01:59Z")));
+        assert!(bounds.contains(ts("2025-01-01T14:02:00Z")));
+        assert!(!bounds.contains(ts("2025-01-02T00:00:00Z")));
+
+        let zone = TimeZone::get("Europe/Copenhagen").unwrap();
+        let bounds = range.resolve(ts("2025-01-01T09:30:00Z"), &zone);
+        assert_eq!(bounds.since, Some(ts("2025-01-01T13:02:00Z")));
+
+        let bounds = TimeRange::default()
+            .with_since("1h".parse().unwrap())
+            .resolve(ts("2025-01-01T09:30:00Z"), &TimeZone::UTC);
+        let an_hour_ago = Timestamp::now() - SignedDuration::from_hours(1);
+        let since = bounds.since.unwrap();
+        assert!(since <= an_hour_ago && since > an_hour_ago - SignedDuration::from_mins(1));
+        assert_eq!(bounds.until, None);
+    }
+
+    #[test]
+    fn test_seek_offset() {
+        let path = std::env::temp_dir().join(format!("jl-pretty-{}-seek", std::process::id()));
+        let mut content = String::new();
+        let mut offsets = Vec::new();
+        for second in 0..20_000 {
+            offsets.push(content.len() as u64);
+            content.push_str(&format!("{second} line number {second}\n"));
+            if second % 7 == 0 {
+                content.push_str("no timestamp here\n");
+            }
+        }
+        std::fs::File::create(&path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .unwrap();
+
+        let mut file = File::open(&path).unwrap();
+        for second in [0, 1, 9_999, 15_000, 19_999, 25_000] {
+            let since = Timestamp::from_second(second).unwrap();
+            let offset = seek_offset(&mut file, since, seconds_of).unwrap();
+            // we land on a line start, before the line we want but not far
+            let target = offsets
+                .get(second as usize)
+                .copied()
+                .unwrap_or(content.len() as u64);
+            assert!(offset <= target, "{offset} > {target}");
+            assert!(target - offset <= 2 * LINEAR_SCAN_SIZE);
+            assert!(offset == 0 || content.as_bytes()[offset as usize - 1] == b'\n');
+            assert_eq!(
+                count_lines(&mut file, offset).unwrap(),
+                content[..offset as usize].matches('\n').count()
+            );
+        }
+        assert_eq!(
+            last_timestamp(&mut file, seconds_of).unwrap(),
+            Timestamp::from_second(19_999).ok()
+        );
+        assert_eq!(
+            head_lines(&mut file, 2).unwrap(),
+            ["0 line number 0", "no timestamp here"]
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_last_line_before() {
+        let path = std::env::temp_dir().join(format!("jl-pretty-{}-last", std::process::id()));
+        let mut content = String::new();
+        let mut offsets = Vec::new();
+        for second in 0..20_000 {
+            offsets.push(content.len() as u64);
+            content.push_str(&format!("{second} line number {second}\n"));
+        }
+        std::fs::File::create(&path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .unwrap();
+
+        let mut file = File::open(&path).unwrap();
+        let every = |n: u64| {
+            move |line: &str| line.split_once(' ').unwrap().0.parse::<u64>().unwrap() % n == 0
+        };
+        for (before, n, found) in [
+            (19_999, 5_000, Some(15_000)),
+            (15_000, 5_000, Some(10_000)),
+            (15_001, 5_000, Some(15_000)),
+            (3, 1, Some(2)),
+            (1, 5_000, Some(0)),
+            (0, 1, None),
+        ] {
+            let last = last_line_before(&mut file, offsets[before], every(n)).unwrap();
+            let expected =
+                found.map(|idx: usize| (offsets[idx], format!("{idx} line number {idx}")));
+            assert_eq!(last, expected, "before line {before}");
+        }
+        let none = last_line_before(&mut file, content.len() as u64, |_| false).unwrap();
+        assert_eq!(none, None);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,509 @@
+use std::fmt::Write;
+
+use jiff::{Timestamp, tz::TimeZone};
+
+// --------------------------------------------------------------------------
+
+/// How the timestamps of a log are encoded.
+///
+/// String timestamps are taken to be ISO 8601 / RFC 3339, with or without an
+/// offset (no offset means UTC) and with a 'T' or a space between the date and
+/// the time. The encoding decides how numeric timestamps (or numeric strings)
+/// are interpreted.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampEncoding {
+    /// ISO 8601 / RFC 3339 strings, i.e. '2025-01-01T23:34:56.987Z'. Numbers
+    /// are taken to be seconds, milliseconds, microseconds or nanoseconds
+    /// since the Unix epoch, depending on their magnitude. Numbers too
+    /// small to be a timestamp are shown as they are.
+    Iso8601,
+    /// Seconds since the Unix epoch, possibly with a fraction
+    EpochSeconds,
+    /// Milliseconds since the Unix epoch
+    EpochMillis,
+    /// Microseconds since the Unix epoch
+    EpochMicros,
+}
+
+impl TimestampEncoding {
+    /// Writes the time part of the given timestamp to `dest`, in UTC, i.e.
+    /// '2025-01-01T23:34:56.987Z' is written as '23:34:56.987Z'. Timestamps
+    /// that can not be parsed are written as they are.
+    pub(crate) fn write_time(&self, dest: &mut String, raw: &str) {
+        // times in UTC are copied without parsing them, with the fraction
+        // padded or cut to milliseconds like the times we format, and marked
+        // as UTC whether they were written with a 'Z' or without an offset
+        if is_utc_iso(raw) {
+            let time = raw[11..].trim_end_matches(['Z', 'z']);
+            let (seconds, fraction) = time.split_once('.').unwrap_or((time, ""));
+            dest.push_str(seconds);
+            dest.push('.');
+            dest.push_str(&fraction[..fraction.len().min(3)]);
+            dest.extend(std::iter::repeat_n(
+                '0',
+                3usize.saturating_sub(fraction.len()),
+            ));
+            dest.push('Z');
+            return;
+        }
+        match self.parse(raw) {
+            Some(ts) => write!(dest, "{}", ts.strftime("%H:%M:%S%.3fZ")).unwrap(),
+            None => dest.push_str(raw),
+        }
+    }
+
+    /// Writes the date part of the given timestamp to `dest`, in UTC, i.e.
+    /// '2025-01-01T23:34:56.987Z' is written as '2025-01-01'. Timestamps that
+    /// can not be parsed are written as they are.
+    pub(crate) fn write_date(&self, dest: &mut String, raw: &str) {
+        match self.parse(raw) {
+            Some(ts) => write!(dest, "{}", ts.strftime("%Y-%m-%d")).unwrap(),
+            None => dest.push_str(raw),
+        }
+    }
+
+    /// Converts a timestamp to nanoseconds since the Unix epoch, for ordering
+    /// lines by time. Returns `None` if the timestamp can not be parsed.
+    pub(crate) fn to_nanos(self, raw: &str) -> Option<i128> {
+        self.parse(raw).map(|ts| ts.as_nanosecond())
+    }
+
+    /// Parses a timestamp, numeric or ISO 8601. Returns `None` if the
+    /// timestamp can not be parsed.
+    pub(crate) fn parse(self, raw: &str) -> Option<Timestamp> {
+        self.to_timestamp(raw).or_else(|| parse_iso(raw))
+    }
+
+    /// Converts a numeric timestamp to a [`Timestamp`]. Returns `None` for
+    /// values that are not numbers (or out of range).
+    fn to_timestamp(self, raw: &str) -> Option<Timestamp> {
+        let nanos_per_unit = match self {
+            Self::Iso8601 => guess_epoch_unit(raw)?,
+            Self::EpochSeconds => 1_000_000_000,
+            Self::EpochMillis => 1_000_000,
+            Self::EpochMicros => 1_000,
+        };
+        let nanos = parse_epoch(raw, nanos_per_unit)?;
+        Timestamp::from_nanosecond(nanos).ok()
+    }
+}
+
+/// Parses an ISO 8601 / RFC 3339 timestamp, taking timestamps without an
+/// offset to be in UTC
+pub(crate) fn parse_iso(raw: &str) -> Option<Timestamp> {
+    raw.parse::<Timestamp>().ok().or_else(|| {
+        let datetime = raw.parse::<jiff::civil::DateTime>().ok()?;
+        datetime
+            .to_zoned(TimeZone::UTC)
+            .ok()
+            .map(|zoned| zoned.timestamp())
+    })
+}
+
+/// Checks if a timestamp is written as an ISO 8601 time in UTC, i.e.
+/// '2025-01-01T23:34:56.987Z' (with or without the 'Z'), so the time part can
+/// be copied from it. Only the shape of it is checked.
+fn is_utc_iso(raw: &str) -> bool {
+    const SHAPE: &[u8] = b"0000-00-00T00:00:00";
+    let Some((head, rest)) = raw.as_bytes().split_at_checked(SHAPE.len()) else {
+        return false;
+    };
+    let is_digits = |bytes: &[u8]| bytes.iter().all(u8::is_ascii_digit);
+    let head_matches = head.iter().zip(SHAPE).all(|(b, shape)| match shape {
+        b'0' => b.is_ascii_digit(),
+        b'T' => matches!(b, b'T' | b't' | b' '),
+        _ => b == shape,
+    });
+    let rest = rest
+        .strip_suffix(b"Z")
+        .or(rest.strip_suffix(b"z"))
+        .unwrap_or(rest);
+    let fraction_matches = match rest {
+        [] => true,
+        [b'.', digits @ ..] => !digits.is_empty() && is_digits(digits),
+        _ => false,
+    };
+    head_matches && fraction_matches
+}
+
+/// Guesses the unit of an epoch timestamp from its magnitude, as the number of
+/// nanoseconds per unit. Seconds cover the years 1973 to 5138, milliseconds,
+/// microseconds and nanoseconds the same span after that. Smaller numbers, like
+/// a bare year, are not taken to be timestamps.
+fn guess_epoch_unit(raw: &str) -> Option<i128> {
+    let units = parse_epoch(raw, 1)?.unsigned_abs();
+    Some(match units {
+        0..100_000_000 => return None,
+        100_000_000..100_000_000_000 => 1_000_000_000,
+        100_000_000_000..100_000_000_000_000 => 1_000_000,
+        100_000_000_000_000..100_000_000_000_000_000 => 1_000,
+        _ => 1,
+    })
+}
+
+// --------------------------------------------------------------------------
+
+/// How to display timestamps.
+///
+/// By default the time of day is shown in UTC, to the millisecond, copied from
+/// the log where it is written in UTC already. Setting a time zone, a format or asking for the date makes us parse the
+/// timestamps and format them ourselves. Timestamps we can not parse are shown
+/// as they are.
+///
+#[derive(Debug, Clone, Default)]
+pub struct TimeFormat {
+    zone: Option<TimeZone>,
+    format: Option<String>,
+    date: bool,
+}
+
+impl TimeFormat {
+    /// Show the times in the given time zone
+    pub fn with_zone(mut self, zone: TimeZone) -> Self {
+        self.zone = Some(zone);
+        self
+    }
+
+    /// Show the times using the given strftime-style format, i.e. '%H:%M:%S'
+    pub fn with_format(mut self, format: impl Into<String>) -> Self {
+        self.format = Some(format.into());
+        self
+    }
+
+    /// Show the date in front of the time (ignored with a custom format)
+    pub fn with_date(mut self, date: bool) -> Self {
+        self.date = date;
+        self
+    }
+
+    /// The time zone the times are shown in, UTC unless told otherwise
+    pub(crate) fn zone(&self) -> TimeZone {
+        self.zone.clone().unwrap_or(TimeZone::UTC)
+    }
+
+    /// Checks if the timestamps are shown as the time of day in UTC
+    fn is_default(&self) -> bool {
+        self.zone.is_none() && self.format.is_none() && !self.date
+    }
+
+    /// Writes the timestamp to `dest`, according to the format
+    pub(crate) fn write_time(&self, dest: &mut String, encoding: TimestampEncoding, raw: &str) {
+        let ts = match self.is_default() {
+            true => None,
+            false => encoding.parse(raw),
+        };
+        let Some(ts) = ts else {
+            encoding.write_time(dest, raw);
+            return;
+        };
+
+        let zone = self.zone();
+        let is_utc = zone == TimeZone::UTC;
+        let zoned = ts.to_zoned(zone);
+        match (&self.format, self.date, is_utc) {
+            (Some(format), _, _) => write!(dest, "{}", zoned.strftime(format)),
+            (None, true, true) => write!(dest, "{}", zoned.strftime("%Y-%m-%d %H:%M:%S%.3fZ")),
+            (None, true, false) => write!(dest, "{}", zoned.strftime("%Y-%m-%d %H:%M:%S%.3f")),
+            (None, false, true) => write!(dest, "{}", zoned.strftime("%H:%M:%S%.3fZ")),
+            (None, false, false) => write!(dest, "{}", zoned.strftime("%H:%M:%S%.3f")),
+        }
+        // invalid formats are rejected up front, see `validate_format`
+        .unwrap_or_default();
+    }
+
+    /// Writes the date of the timestamp to `dest`, in the time zone of the
+    /// format
+    pub(crate) fn write_date(&self, dest: &mut String, encoding: TimestampEncoding, raw: &str) {
+        match (&self.zone, encoding.parse(raw)) {
+            (Some(zone), Some(ts)) => {
+                write!(dest, "{}", ts.to_zoned(zone.clone()).strftime("%Y-%m-%d")).unwrap()
+            }
+            _ => encoding.write_date(dest, raw),
+        }
+    }
+}
+
+/// Checks that a strftime-style format can be used to format timestamps
+pub fn validate_format(format: &str) -> Result<(), String> {
+    let zoned = Timestamp::UNIX_EPOCH.to_zoned(TimeZone::UTC);
+    jiff::fmt::strtime::format(format, &zoned)
+        .map(|_| ())
+        .map_err(|err| format!("invalid time format '{format}': {err}"))
+}
+
+/// Looks up a time zone by name, where 'local' is the time zone of the system
+pub fn time_zone(name: &str) -> Result<TimeZone, String> {
+    if name.eq_ignore_ascii_case("local") {
+        return Ok(TimeZone::system());
+    }
+    if name.eq_ignore_ascii_case("utc") {
+        return Ok(TimeZone::UTC);
+    }
+    TimeZone::get(name).map_err(|err| format!("unknown time zone '{name}': {err}"))
+}
+
+// --------------------------------------------------------------------------
+
+/// Parses a (possibly fractional) decimal number of epoch units to
+/// nanoseconds. Handles exponent notation too, like the '1.7127807011334e+09'
+/// that zap emits.
+fn parse_epoch(raw: &str, nanos_per_unit: i128) -> Option<i128> {
+    let (negative, raw) = match raw.strip_prefix('-') {
+        Some(raw) => (true, raw),
+        None => (false, raw),
+    };
+    let (mantissa, exponent) = match raw.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i32>().ok()?),
+        None => (raw, 0),
+    };
+    let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let all_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if int_part.is_empty() || !all_digits(int_part) || !all_digits(frac_part) {
+        return None;
+    }
+
+    // move the decimal point according to the exponent, so we are left with
+    // an integer number of units and the digits of the fraction
+    let digits = [int_part, frac_part].concat();
+    let point = int_part.len() as i32 + exponent;
+    if !(0..=24).contains(&point) {
+        return None;
+    }
+    let (int_digits, frac_digits) = digits.split_at((point as usize).min(digits.len()));
+    let padding = (point as usize).saturating_sub(digits.len());
+
+    let mut nanos = match int_digits {
+        "" => 0,
+        int_digits => int_digits.parse::<i128>().ok()? * 10i128.pow(padding as u32),
+    } * nanos_per_unit;
+    // only the fraction digits that fit within a nanosecond matter
+    let mut scale = nanos_per_unit;
+    for digit in frac_digits.bytes().take(9) {
+        scale /= 10;
+        nanos += i128::from(digit - b'0') * scale;
+    }
+    Some(if negative { -nanos } else { nanos })
+}
+
+// --------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::xorshift;
+
+    fn time(encoding: TimestampEncoding, raw: &str) -> String {
+        let mut dest = String::new();
+        encoding.write_time(&mut dest, raw);
+        dest
+    }
+
+    fn date(encoding: TimestampEncoding, raw: &str) -> String {
+        let mut dest = String::new();
+        encoding.write_date(&mut dest, raw);
+        dest
+    }
+
+    #[test]
+    fn test_iso_timestamps() {
+        let raw = "2025-04-10T20:25:01.133Z";
+        assert_eq!(time(TimestampEncoding::Iso8601, raw), "20:25:01.133Z");
+        assert_eq!(date(TimestampEncoding::Iso8601, raw), "2025-04-10");
+        // ISO strings are accepted by the epoch encodings too
+        assert_eq!(time(TimestampEncoding::EpochMillis, raw), "20:25:01.133Z");
+    }
+
+    #[test]
+    fn test_epoch_timestamps() {
+        use TimestampEncoding::*;
+        assert_eq!(time(EpochMillis, "1744316701133"), "20:25:01.133Z");
+        assert_eq!(time(EpochSeconds, "1744316701.133"), "20:25:01.133Z");
+        assert_eq!(time(EpochSeconds, "1.744316701133e9"), "20:25:01.133Z");
+        assert_eq!(time(EpochMicros, "1744316701133000"), "20:25:01.133Z");
+        assert_eq!(date(EpochMillis, "1744316701133"), "2025-04-10");
+    }
+
+    #[test]
+    fn test_lenient_iso_timestamps() {
+        use TimestampEncoding::*;
+        // offsets are converted to UTC, no offset means UTC
+        assert_eq!(
+            time(Iso8601, "2025-04-10T22:25:01.133+02:00"),
+            "20:25:01.133Z"
+        );
+        assert_eq!(
+            time(Iso8601, "2025-04-10T20:25:01.133-00:30"),
+            "20:55:01.133Z"
+        );
+        assert_eq!(time(Iso8601, "2025-04-10 20:25:01.133Z"), "20:25:01.133Z");
+        assert_eq!(time(Iso8601, "2025-04-10 20:25:01"), "20:25:01.000Z");
+        assert_eq!(time(Iso8601, "2025-04-10t20:25:01.5z"), "20:25:01.500Z");
+        assert_eq!(date(Iso8601, "2025-04-10T23:25:01-02:00"), "2025-04-11");
+    }
+
+    #[test]
+    fn test_utc_iso_shape() {
+        assert!(is_utc_iso("2025-04-10T20:25:01.133Z"));
+        assert!(is_utc_iso("2025-04-10 20:25:01"));
+        assert!(is_utc_iso("2025-04-10t20:25:01.5z"));
+        assert!(!is_utc_iso("2025-04-10T20:25:01.133+02:00"));
+        assert!(!is_utc_iso("2025-04-10T20:25:01."));
+        assert!(!is_utc_iso("2025-04-10T20:25"));
+        assert!(!is_utc_iso("2025-04-10Tæøå:01Z"));
+    }
+
+    #[test]
+    fn test_guessed_epoch_units() {
+        use TimestampEncoding::*;
+        assert_eq!(time(Iso8601, "1744316701"), "20:25:01.000Z");
+        assert_eq!(time(Iso8601, "1744316701.133"), "20:25:01.133Z");
+        assert_eq!(time(Iso8601, "1744316701133"), "20:25:01.133Z");
+        assert_eq!(time(Iso8601, "1744316701133000"), "20:25:01.133Z");
+        assert_eq!(time(Iso8601, "1744316701133000000"), "20:25:01.133Z");
+        // too small to be a timestamp, like a bare year
+        assert_eq!(time(Iso8601, "2023"), "2023");
+        assert_eq!(time(Iso8601, "0"), "0");
+        assert_eq!(Iso8601.to_nanos("2023"), None);
+        // unless the encoding says so
+        assert_eq!(time(EpochSeconds, "2023"), "00:33:43.000Z");
+    }
+
+    #[test]
+    fn test_unparseable_timestamps_are_verbatim() {
+        use TimestampEncoding::*;
+        for raw in [
+            "",
+            "yesterday",
+            "2025-01-01T1",
+            "2025-01-01Tæøå",
+            "1e400",
+            "-",
+        ] {
+            assert_eq!(time(Iso8601, raw), raw);
+            assert_eq!(time(EpochMillis, raw), raw);
+            assert_eq!(date(EpochSeconds, raw), raw);
+        }
+    }
+
+    #[test]
+    fn test_fuzz_timestamps() {
+        use TimestampEncoding::*;
+        const ALPHABET: &[u8] = b"0123456789-+:.TZtz eE";
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..5_000 {
+            let len = (xorshift(&mut state) % 40) as usize;
+            let raw = (0..len)
+                .map(|_| match xorshift(&mut state) % 20 {
+                    // the odd multi-byte char, to trip up slicing
+                    0 => 'ø',
+                    n => ALPHABET[(n as usize * 7 + len) % ALPHABET.len()] as char,
+                })
+                .collect::<String>();
+            for encoding in [Iso8601, EpochSeconds, EpochMillis, EpochMicros] {
+                time(encoding, &raw);
+                date(encoding, &raw);
+                encoding.to_nanos(&raw);
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_nanos() {
+        use TimestampEncoding::*;
+        let nanos = 1_744_316_701_133_000_000;
+        assert_eq!(Iso8601.to_nanos("2025-04-10T20:25:01.133Z"), Some(nanos));
+        assert_eq!(EpochMillis.to_nanos("1744316701133"), Some(nanos));
+        assert_eq!(
+            EpochMillis.to_nanos("2025-04-10T20:25:01.133Z"),
+            Some(nanos)
+        );
+        assert_eq!(Iso8601.to_nanos("yesterday"), None);
+    }
+
+    fn formatted(format: &TimeFormat, encoding: TimestampEncoding, raw: &str) -> String {
+        let mut dest = String::new();
+        format.write_time(&mut dest, encoding, raw);
+        dest
+    }
+
+    #[test]
+    fn test_time_format_default() {
+        use TimestampEncoding::*;
+        let format = TimeFormat::default();
+        assert_eq!(
+            formatted(&format, Iso8601, "2025-04-10T20:25:01Z"),
+            "20:25:01.000Z"
+        );
+        assert_eq!(
+            formatted(&format, EpochMillis, "1744316701133"),
+            "20:25:01.133Z"
+        );
+        // all times are shown with millisecond precision, however they are
+        // written
+        assert_eq!(
+            formatted(&format, Iso8601, "2025-04-10T20:25:01.133999Z"),
+            "20:25:01.133Z"
+        );
+        assert_eq!(
+            formatted(&format, Iso8601, "2025-04-10T20:25:01.133999+00:00"),
+            "20:25:01.133Z"
+        );
+    }
+
+    #[test]
+    fn test_time_format_with_zone() {
+        use TimestampEncoding::*;
+        let format = TimeFormat::default().with_zone(time_zone("Europe/Copenhagen").unwrap());
+        assert_eq!(
+            formatted(&format, Iso8601, "2025-04-10T20:25:01Z"),
+            "22:25:01.000"
+        );
+        assert_eq!(
+            formatted(&format, EpochMillis, "1744316701133"),
+            "22:25:01.133"
+        );
+        // timestamps without an offset are in UTC
+        assert_eq!(
+            formatted(&format, Iso8601, "2025-04-10T20:25:01"),
+            "22:25:01.000"
+        );
+        // unparseable timestamps are shown as they are
+        assert_eq!(formatted(&format, Iso8601, "noon"), "noon");
+
+        let mut dest = String::new();
+        format.write_date(&mut dest, Iso8601, "2025-04-10T23:25:01Z");
+        assert_eq!(dest, "2025-04-11");
+    }
+
+    #[test]
+    fn test_time_format_with_format_and_date() {
+        use TimestampEncoding::*;
+        let raw = "2025-04-10T20:25:01.5Z";
+        let format = TimeFormat::default().with_date(true);
+        assert_eq!(formatted(&format, Iso8601, raw), "2025-04-10 20:25:01.500Z");
+        let format = TimeFormat::default().with_format("%d/%m %H:%M");
+        assert_eq!(formatted(&format, Iso8601, raw), "10/04 20:25");
+    }
+
+    #[test]
+    fn test_validate_format_and_time_zone() {
+        assert!(validate_format("%H:%M:%S").is_ok());
+        assert!(validate_format("%!").is_err());
+        assert!(time_zone("local").is_ok());
+        assert!(time_zone("UTC").is_ok());
+        assert!(time_zone("Mars/Olympus_Mons").is_err());
+    }
+
+    #[test]
+    fn test_parse_epoch() {
+        assert_eq!(parse_epoch("1", 1_000_000), Some(1_000_000));
+        assert_eq!(parse_epoch("1.5", 1_000_000), Some(1_500_000));
+        assert_eq!(parse_epoch("-2.25", 1_000_000_000), Some(-2_250_000_000));
+        assert_eq!(parse_epoch("", 1_000), None);
+        assert_eq!(parse_epoch("12a", 1_000), None);
+        assert_eq!(parse_epoch("1.2.3", 1_000), None);
+        assert_eq!(parse_epoch("1.5e3", 1), Some(1500));
+        assert_eq!(parse_epoch("15e-1", 10), Some(15));
+    }
+}
@@ -1,20 +1,45 @@
 use std::{
     fs::File,
-    io::{BufReader, IsTerminal},
-    path::PathBuf,
+    io::{BufRead, BufReader, IsTerminal, Write},
+    path::{Path, PathBuf},
     process::ExitCode,
 };
 
-use clap::Parser;
-use pretty::{LineProcessor, ProcessError, SessionStartDetector};
+use clap::{Parser, ValueEnum, builder::PossibleValue};
+use pretty::{
+    Compression, DEFAULT_MERGE_WINDOW, DecompressReader, ExtraFields, FORMAT_DETECTION_SAMPLE_SIZE,
+    FieldMap, FieldPath, FollowReader, Format, Framing, Grep, InvalidLines, Level, LevelFilter,
+    LinePrefix, LineProcessor, NestedStyle, ProcessError, Query, SessionStartDetector, TimeBound,
+    TimeFormat, TimeMode, TimeRange,
+};
+use regex::Regex;
 
 // --------------------------------------------------------------------------
 
 fn main() -> ExitCode {
     let cli = Cli::parse();
 
-    // TODO: let this be controlled by args
-    let session_detector = SessionStartDetector::from_message("Motion server startup initiated");
+    let inputs = match cli.inputs() {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if cli.follow && inputs.len() != 1 {
+        eprintln!("--follow needs exactly one input file");
+        return ExitCode::FAILURE;
+    }
+    if cli.follow && matches!(cli.format, FormatChoice::Auto) {
+        eprintln!("--follow can not detect the log format, give it with '--format'");
+        return ExitCode::FAILURE;
+    }
+    if cli.merge && inputs.is_empty() {
+        eprintln!("--merge needs input files");
+        return ExitCode::FAILURE;
+    }
+
+    let session_detector = cli.session_detector();
 
     // grab and lock stdout
     let stdout = std::io::stdout();
@@ -29,17 +54,57 @@ fn main() -> ExitCode {
         (is_term && !cli.no_color) || (!is_term && cli.use_color)
     };
 
-    let processor = LineProcessor::new(session_detector, cli.skip_invalid_lines, use_color);
+    let processor = LineProcessor::new(session_detector, cli.skip_invalid_lines, use_color)
+        .with_invalid_lines(cli.invalid_lines())
+        .with_prefix(cli.prefix)
+        .with_framing(cli.framing)
+        .with_fields(cli.field_overrides())
+        .with_collapse_stack(cli.collapse_stack)
+        .with_level_filter(cli.level_filter())
+        .with_filename(cli.with_filename)
+        .with_merge_window(cli.merge_window)
+        .with_time_format(cli.time_format())
+        .with_time_mode(cli.time)
+        .with_time_range(cli.time_range())
+        .with_follow(cli.follow)
+        .with_grep(cli.grep())
+        .with_verbose(cli.verbose);
+    let processor = match cli.highlight_delta {
+        Some(threshold) => processor.with_highlight_delta(threshold),
+        None => processor,
+    };
+    let processor = match cli.where_query.clone() {
+        Some(query) => processor.with_query(query),
+        None => processor,
+    };
+    let processor = match cli.gap {
+        Some(threshold) => processor.with_gap(threshold),
+        None => processor,
+    };
+    let processor = match cli.extra_fields() {
+        Some(extra_fields) => processor.with_extra_fields(extra_fields),
+        None => processor,
+    };
+    let processor = match cli.format {
+        FormatChoice::Auto => processor.with_format_detection(FORMAT_DETECTION_SAMPLE_SIZE),
+        FormatChoice::Fixed(format) => processor.with_format(format),
+    };
 
-    // we either use the given file or std in as input
-    let result = if let Some(input_file) = cli.input_file {
-        match File::open(&input_file) {
-            Ok(file) => processor.process_lines(BufReader::new(file), &mut output),
-            Err(err) => Err(ProcessError::InputNotFound(input_file, err)),
+    // we either merge the given files, use them in order, or use std in as
+    // input
+    let result = if cli.merge {
+        merge_files(&processor, &inputs, cli.verbose, &mut output)
+    } else if inputs.is_empty() {
+        let stdin_handle = std::io::stdin();
+        match decompress(stdin_handle.lock(), "std in", cli.verbose) {
+            Ok(reader) => processor.process_lines(reader, &mut output),
+            Err(err) => Err(ProcessError::StdinError(err)),
         }
     } else {
-        let stdin_handle = std::io::stdin();
-        processor.process_lines(stdin_handle.lock(), &mut output)
+        inputs.iter().try_for_each(|path| {
+            let follow = cli.follow.then_some(cli.lines);
+            process_file(&processor, path, follow, cli.verbose, &mut output)
+        })
     };
 
     match result {
@@ -51,15 +116,125 @@ fn main() -> ExitCode {
     }
 }
 
+/// Processes a single input file. With `follow` we keep reading as the file
+/// grows, optionally starting at the given number of lines from the end.
+/// Otherwise the file is decompressed, if it is compressed.
+fn process_file<W: Write>(
+    processor: &LineProcessor,
+    path: &Path,
+    follow: Option<Option<usize>>,
+    verbose: bool,
+    output: &mut W,
+) -> Result<(), ProcessError> {
+    let not_found = |err| ProcessError::InputNotFound(path.to_path_buf(), err);
+    match follow {
+        Some(lines) => {
+            let reader = FollowReader::open(path)
+                .and_then(|reader| match lines {
+                    Some(lines) => reader.with_last_lines(lines),
+                    None => Ok(reader),
+                })
+                .map_err(not_found)?;
+            processor.process_file_lines(path, BufReader::new(reader), output)
+        }
+        None => {
+            let mut file = File::open(path).map_err(not_found)?;
+            let skipped = processor.seek_to_time_range(path, &mut file)?;
+            if verbose && skipped.bytes > 0 {
+                let (lines, bytes) = (skipped.lines, skipped.bytes);
+                eprintln!(
+                    "--- skipped {lines} lines ({bytes} bytes) of '{}' ---",
+                    path.display()
+                );
+            }
+            let reader =
+                decompress(BufReader::new(file), path.display(), verbose).map_err(not_found)?;
+            processor.process_file_lines_after(path, skipped, reader, output)
+        }
+    }
+}
+
+/// Processes the input files concurrently, merging their lines by timestamp
+fn merge_files<W: Write>(
+    processor: &LineProcessor,
+    paths: &[PathBuf],
+    verbose: bool,
+    output: &mut W,
+) -> Result<(), ProcessError> {
+    let mut files = Vec::with_capacity(paths.len());
+    for path in paths {
+        let reader = open_decompressed(path, verbose)
+            .map_err(|err| ProcessError::InputNotFound(path.clone(), err))?;
+        files.push((path.as_path(), reader));
+    }
+    processor.process_merged(files, output)
+}
+
+/// Opens a file, decompressing it if it is compressed
+fn open_decompressed(
+    path: &Path,
+    verbose: bool,
+) -> std::io::Result<DecompressReader<BufReader<File>>> {
+    let file = File::open(path)?;
+    decompress(BufReader::new(file), path.display(), verbose)
+}
+
+/// Wraps an input in a reader that decompresses it, if it is compressed
+fn decompress<R: BufRead>(
+    reader: R,
+    name: impl std::fmt::Display,
+    verbose: bool,
+) -> std::io::Result<DecompressReader<R>> {
+    let reader = DecompressReader::new(reader)?;
+    let compression = reader.compression();
+    if verbose && compression != Compression::None {
+        eprintln!(
+            "--- reading {} compressed input '{name}' ---",
+            compression.name()
+        );
+    }
+    Ok(reader)
+}
+
 // --------------------------------------------------------------------------
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
-    /// File to read from, if not given std in is used
+    /// Files to read from, in order, or glob patterns matching them (i.e.
+    /// 'logs/*.jsonl'). If none are given std in is used
+    #[arg(value_name = "FILES")]
+    files: Vec<String>,
+
+    /// File to read from, before any other files
     #[arg(short, long, value_name = "FILE")]
     input_file: Option<PathBuf>,
 
+    /// Prefix each line with the name of the file it came from
+    #[arg(long)]
+    with_filename: bool,
+
+    /// Read the input files concurrently and interleave their lines by timestamp,
+    /// each tagged with the name of its file
+    #[arg(short, long)]
+    merge: bool,
+
+    /// With --merge, how many lines of each file may be out of order and still
+    /// be merged in order
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_MERGE_WINDOW)]
+    merge_window: usize,
+
+    /// Keep reading the input file as it grows, following it across truncation
+    /// and log rotation (like 'tail -F'). The format is not detected, give it
+    /// with '--format'.
+    #[arg(short, long, conflicts_with = "merge")]
+    follow: bool,
+
+    /// With --follow, start at the last N lines of the file instead of at the
+    /// beginning (line numbers in errors then count from there)
+    #[arg(short = 'n', long, value_name = "N", requires = "follow")]
+    lines: Option<usize>,
+
     /// Force color output, even if std out is not a terminal
     #[arg(long, conflicts_with = "no_color")]
     use_color: bool,
@@ -68,7 +243,356 @@ struct Cli {
     #[arg(long)]
     no_color: bool,
 
-    /// Skip invalid JSON lines in input (default behavior is to abort)
-    #[arg(long)]
+    /// What to do about lines that are not JSON: stop with an error, skip them,
+    /// or show them as they are (plain or dimmed) where they are in the log
+    #[arg(long, value_name = "POLICY", value_enum)]
+    invalid_lines: Option<InvalidLines>,
+
+    /// Skip invalid JSON lines in input, same as '--invalid-lines skip'
+    #[arg(long, conflicts_with = "invalid_lines")]
     skip_invalid_lines: bool,
+
+    /// A new session starts when a message is exactly equal to this text
+    #[arg(
+        long,
+        value_name = "TEXT",
+        default_value = DEFAULT_SESSION_START,
+        conflicts_with_all = ["session_start_regex", "no_sessions"]
+    )]
+    session_start: String,
+
+    /// A new session starts when a message matches this regular expression
+    #[arg(long, value_name = "RX", conflicts_with = "no_sessions")]
+    session_start_regex: Option<Regex>,
+
+    /// Do not detect new sessions in the log
+    #[arg(long)]
+    no_sessions: bool,
+
+    /// JSON path(s) of the stack trace field, tried in order (overrides the format)
+    #[arg(long, value_name = "PATH", value_delimiter = ',')]
+    stack_field: Vec<FieldPath>,
+
+    /// Collapse stack frames from library code (i.e. node_modules) into a single line
+    #[arg(long)]
+    collapse_stack: bool,
+
+    /// Show all the remaining fields of each line after the message
+    #[arg(short, long)]
+    all_fields: bool,
+
+    /// Show only these fields after the message
+    #[arg(long, value_name = "PATH", value_delimiter = ',')]
+    fields: Vec<FieldPath>,
+
+    /// Do not show these fields after the message, nested ones like 'err.stack' included
+    /// (implies --all-fields, unless --fields is given)
+    #[arg(long, value_name = "PATH", value_delimiter = ',')]
+    hide_fields: Vec<FieldPath>,
+
+    /// How to show fields holding objects or arrays
+    #[arg(long, default_value = "inline", value_enum)]
+    nested: NestedStyle,
+
+    /// Show timestamps in this time zone, i.e. 'local', 'UTC' or 'Europe/Copenhagen'
+    /// (by default they are shown as written in the log)
+    #[arg(long, value_name = "ZONE", value_parser = pretty::time_zone)]
+    tz: Option<jiff::tz::TimeZone>,
+
+    /// Show timestamps in this strftime-style format, i.e. '%H:%M:%S%.3f'
+    #[arg(long, value_name = "FORMAT", value_parser = parse_time_format)]
+    time_format: Option<String>,
+
+    /// Show the date along with the time of each line
+    #[arg(long)]
+    date: bool,
+
+    /// What to show in the time column: the timestamp of each line, the time
+    /// since the first line, since the previous line or since the session started
+    #[arg(long, default_value = "absolute", value_enum)]
+    time: TimeMode,
+
+    /// Highlight the time of lines coming more than this long after the line
+    /// before them, i.e. '500ms' or '2s'
+    #[arg(long, value_name = "DURATION")]
+    highlight_delta: Option<jiff::SignedDuration>,
+
+    /// Mark pauses longer than this between lines with a separator line,
+    /// i.e. '30s' or '5m'
+    #[arg(long, value_name = "DURATION")]
+    gap: Option<jiff::SignedDuration>,
+
+    /// Show only lines at or after this time: a timestamp, a time of day on the
+    /// date of the first line (i.e. '14:02') or a duration ago (i.e. '15m').
+    /// Sorted files are searched for the start
+    #[arg(long, value_name = "TIME")]
+    since: Option<TimeBound>,
+
+    /// Show only lines before this time, given like '--since'
+    #[arg(long, value_name = "TIME")]
+    until: Option<TimeBound>,
+
+    /// Show only lines with this level or a more severe one (lines with unknown
+    /// levels are always shown)
+    #[arg(long, value_name = "LEVEL")]
+    level: Option<Level>,
+
+    /// Show only lines with these levels ('unknown' selects unrecognized levels)
+    #[arg(long, value_name = "LEVEL", value_delimiter = ',')]
+    only_levels: Vec<Level>,
+
+    /// Do not show lines with these levels ('unknown' selects unrecognized levels)
+    #[arg(long, value_name = "LEVEL", value_delimiter = ',')]
+    exclude_levels: Vec<Level>,
+
+    /// Show only lines with a message matching this regular expression
+    #[arg(long, value_name = "RX")]
+    grep: Option<Regex>,
+
+    /// Show only lines with a field matching a regular expression, i.e.
+    /// 'status=^5' (when given more than once, all of them must match)
+    #[arg(long, value_name = "PATH=RX", value_parser = parse_field_pattern)]
+    grep_field: Vec<(FieldPath, Regex)>,
+
+    /// Show only lines with fields matching this expression, i.e.
+    /// 'status >= 500 && path ~ "^/api"'. Paths on their own check that the field
+    /// is there, comparisons with numbers are numeric, and '~' / '!~' match regexes
+    #[arg(long = "where", value_name = "EXPR")]
+    where_query: Option<Query>,
+
+    /// Also show N lines after each matching line
+    #[arg(short = 'A', long, value_name = "N")]
+    after_context: Option<usize>,
+
+    /// Also show N lines before each matching line
+    #[arg(short = 'B', long, value_name = "N")]
+    before_context: Option<usize>,
+
+    /// Also show N lines before and after each matching line
+    #[arg(short = 'C', long, value_name = "N")]
+    context: Option<usize>,
+
+    /// Report what is going on (i.e. the detected log format) on std err
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// The JSON logger that produced the input, 'auto' detects it from the
+    /// first lines of the input. 'journald' unwraps the journal entries and
+    /// detects the format of their messages.
+    #[arg(long, default_value = "winston", value_enum, ignore_case = true)]
+    format: FormatChoice,
+
+    /// The prefix written in front of the JSON by whatever collected the logs
+    /// (containerd, docker compose, kubectl or syslog), or the wrapper written
+    /// around it by docker's json-file log driver or 'journalctl -o json'.
+    /// 'auto' uses the one most of the first lines of each input have.
+    #[arg(long, default_value = "auto", value_enum)]
+    prefix: LinePrefix,
+
+    /// How the input is split into records: one JSON object per line (the
+    /// default, and fastest), or complete JSON values however they are spread
+    /// over lines, i.e. pretty-printed objects. Either way, input starting with
+    /// an array of objects (i.e. '[{') is read as an array of records.
+    #[arg(long, default_value = "lines", value_enum)]
+    framing: Framing,
+
+    /// JSON path(s) of the timestamp field, tried in order (overrides the format)
+    #[arg(long, value_name = "PATH", value_delimiter = ',')]
+    timestamp_field: Vec<FieldPath>,
+
+    /// JSON path(s) of the level field, tried in order (overrides the format)
+    #[arg(long, value_name = "PATH", value_delimiter = ',')]
+    level_field: Vec<FieldPath>,
+
+    /// JSON path(s) of the message field, tried in order (overrides the format)
+    #[arg(long, value_name = "PATH", value_delimiter = ',')]
+    message_field: Vec<FieldPath>,
+}
+
+/// The message that marks a new session, unless told otherwise
+const DEFAULT_SESSION_START: &str = "Motion server startup initiated";
+
+/// The log format selected on the command line
+#[derive(Clone, Copy)]
+enum FormatChoice {
+    /// Detect the format from the input
+    Auto,
+    /// Use the given format
+    Fixed(Format),
+}
+
+/// 'auto' followed by all the formats, in the order they are listed in help
+/// texts
+const FORMAT_CHOICES: [FormatChoice; Format::ALL.len() + 1] = {
+    let mut choices = [FormatChoice::Auto; Format::ALL.len() + 1];
+    let mut idx = 0;
+    while idx < Format::ALL.len() {
+        choices[idx + 1] = FormatChoice::Fixed(Format::ALL[idx]);
+        idx += 1;
+    }
+    choices
+};
+
+impl ValueEnum for FormatChoice {
+    fn value_variants<'a>() -> &'a [Self] {
+        &FORMAT_CHOICES
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        match self {
+            FormatChoice::Auto => Some(PossibleValue::new("auto")),
+            FormatChoice::Fixed(format) => format.to_possible_value(),
+        }
+    }
+}
+
+/// Parses a time format, making sure it can be used
+fn parse_time_format(format: &str) -> Result<String, String> {
+    pretty::validate_format(format).map(|_| format.to_string())
+}
+
+/// Parses a 'path=regex' pair selecting lines by one of their fields
+fn parse_field_pattern(pattern: &str) -> Result<(FieldPath, Regex), String> {
+    let (path, rx) = pattern
+        .split_once('=')
+        .ok_or_else(|| format!("expected PATH=RX, found '{pattern}'"))?;
+    let path = path.parse::<FieldPath>().map_err(|err| err.to_string())?;
+    let rx = Regex::new(rx).map_err(|err| err.to_string())?;
+    Ok((path, rx))
+}
+
+impl Cli {
+    /// Builds the invalid lines policy, from the skip flag unless one is given
+    fn invalid_lines(&self) -> InvalidLines {
+        match (self.invalid_lines, self.skip_invalid_lines) {
+            (Some(invalid_lines), _) => invalid_lines,
+            (None, true) => InvalidLines::Skip,
+            (None, false) => InvalidLines::Abort,
+        }
+    }
+
+    /// Builds the grep settings from the grep and context flags
+    fn grep(&self) -> Grep {
+        let mut grep = Grep::default().with_context(
+            self.before_context.or(self.context).unwrap_or_default(),
+            self.after_context.or(self.context).unwrap_or_default(),
+        );
+        if let Some(rx) = &self.grep {
+            grep = grep.with_message(rx.clone());
+        }
+        for (path, rx) in &self.grep_field {
+            grep = grep.with_field(path.clone(), rx.clone());
+        }
+        grep
+    }
+
+    /// Builds the time range selected by the since and until flags
+    fn time_range(&self) -> TimeRange {
+        let mut time_range = TimeRange::default();
+        if let Some(since) = self.since {
+            time_range = time_range.with_since(since);
+        }
+        if let Some(until) = self.until {
+            time_range = time_range.with_until(until);
+        }
+        time_range
+    }
+
+    /// Builds the time format selected by the time flags
+    fn time_format(&self) -> TimeFormat {
+        let mut time_format = TimeFormat::default().with_date(self.date);
+        if let Some(zone) = &self.tz {
+            time_format = time_format.with_zone(zone.clone());
+        }
+        if let Some(format) = &self.time_format {
+            time_format = time_format.with_format(format.as_str());
+        }
+        time_format
+    }
+
+    /// Collects the input files, expanding any glob patterns
+    fn inputs(&self) -> Result<Vec<PathBuf>, ProcessError> {
+        let mut inputs = self.input_file.iter().cloned().collect::<Vec<_>>();
+        for pattern in &self.files {
+            if !pattern.contains(['*', '?', '[']) {
+                inputs.push(PathBuf::from(pattern));
+                continue;
+            }
+            let not_found = |err| ProcessError::InputNotFound(PathBuf::from(pattern), err);
+            let paths = glob::glob(pattern).map_err(|err| {
+                not_found(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    err.msg,
+                ))
+            })?;
+            let count = inputs.len();
+            for path in paths {
+                inputs.push(path.map_err(|err| not_found(err.into()))?);
+            }
+            if inputs.len() == count {
+                return Err(not_found(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "no files match the pattern",
+                )));
+            }
+        }
+        Ok(inputs)
+    }
+
+    /// Builds the session start detector selected by the session flags
+    fn session_detector(&self) -> SessionStartDetector {
+        if self.no_sessions {
+            SessionStartDetector::never()
+        } else if let Some(rx) = &self.session_start_regex {
+            SessionStartDetector::from_rx(rx.clone())
+        } else {
+            SessionStartDetector::from_message(self.session_start.as_str())
+        }
+    }
+
+    /// Builds the selection of extra fields to show, if any
+    fn extra_fields(&self) -> Option<ExtraFields> {
+        let extra_fields = if !self.fields.is_empty() {
+            ExtraFields::only(self.fields.clone())
+        } else if self.all_fields || !self.hide_fields.is_empty() {
+            ExtraFields::all()
+        } else {
+            return None;
+        };
+        Some(
+            extra_fields
+                .with_hidden(self.hide_fields.clone())
+                .with_nested(self.nested),
+        )
+    }
+
+    /// Builds the filter selected by the level flags
+    fn level_filter(&self) -> LevelFilter {
+        let mut filter = LevelFilter::all();
+        if let Some(level) = self.level {
+            filter = filter.with_min_level(level);
+        }
+        if !self.only_levels.is_empty() {
+            filter = filter.with_only(&self.only_levels);
+        }
+        filter.with_excluded(&self.exclude_levels)
+    }
+
+    /// Builds the field paths that override those of the log format
+    fn field_overrides(&self) -> FieldMap {
+        let mut fields = FieldMap::empty();
+        if !self.timestamp_field.is_empty() {
+            fields = fields.with_timestamp(self.timestamp_field.clone());
+        }
+        if !self.level_field.is_empty() {
+            fields = fields.with_level(self.level_field.clone());
+        }
+        if !self.message_field.is_empty() {
+            fields = fields.with_message(self.message_field.clone());
+        }
+        if !self.stack_field.is_empty() {
+            fields = fields.with_stack(self.stack_field.clone());
+        }
+        fields
+    }
 }